@@ -4,19 +4,24 @@
     allow(dead_code, unused_imports)
 )]
 
-/// mallinfo() Override for CEF - Prevents "Illegal instruction" crash
-///
-/// This shared library intercepts mallinfo() calls and returns safe values
-/// using mallinfo2() on systems with glibc >= 2.33, ensuring that:
-/// 1. No individual field is negative
-/// 2. arena + hblkhd <= INT_MAX
-/// 3. uordblks <= INT_MAX
-///
-/// NOTE: This library is Linux-only. macOS doesn't need it because CEF uses
-/// malloc_zone_statistics() instead of mallinfo() on Apple platforms.
+//! mallinfo() Override for CEF - Prevents "Illegal instruction" crash
+//!
+//! This shared library intercepts mallinfo() calls and returns safe values
+//! using mallinfo2() on systems with glibc >= 2.33 (and glibc's own mallinfo()
+//! read as unsigned on older ones), ensuring that:
+//! 1. No individual field is negative
+//! 2. arena + hblkhd <= INT_MAX
+//! 3. uordblks <= INT_MAX
+//!
+//! NOTE: This library is Linux-only. macOS doesn't need it because CEF uses
+//! malloc_zone_statistics() instead of mallinfo() on Apple platforms.
+//!
+//! The clamping is also available as [`sanitize`]. The `preload` feature (on by
+//! default) adds the `mallinfo` symbol and the load-time constructor; turn it off to
+//! use the crate as a plain Rust library without overriding the consumer's mallinfo().
 
-use std::sync::atomic::{AtomicBool, Ordering};
-use tracing::{debug, warn};
+#[cfg(feature = "preload")]
+mod preload;
 
 /// C struct mallinfo layout (glibc)
 /// signed 32-bit integers
@@ -52,13 +57,9 @@ pub struct Mallinfo2C {
     pub keepcost: usize,
 }
 
-extern "C" {
-    fn mallinfo2() -> Mallinfo2C;
-}
-
-fn clamp_to_int_max(value: i64) -> i32 {
-    if value > i32::MAX as i64 {
-        i32::MAX
+fn clamp_to_limit(value: i64, limit: i32) -> i32 {
+    if value > limit as i64 {
+        limit
     } else if value < 0 {
         0
     } else {
@@ -66,73 +67,118 @@ fn clamp_to_int_max(value: i64) -> i32 {
     }
 }
 
-/// Override mallinfo()
-#[no_mangle]
-pub extern "C" fn mallinfo() -> MallinfoC {
-    static LOGGED_ONCE_ARENA_HBLKHD: AtomicBool = AtomicBool::new(false);
-    static LOGGED_ONCE_UORDBLKS: AtomicBool = AtomicBool::new(false);
-    let info2 = unsafe { mallinfo2() };
+/// Clamps a size_t field, saturating values beyond i64::MAX instead of wrapping them
+pub(crate) fn clamp_size(value: usize, limit: i32) -> i32 {
+    clamp_to_limit(i64::try_from(value).unwrap_or(i64::MAX), limit)
+}
 
+/// Converts mallinfo2() values to the mallinfo() layout the way the override does,
+/// without calling into the allocator or logging
+///
+/// The result guarantees that:
+/// 1. No field is negative, values beyond INT_MAX are clamped to INT_MAX
+/// 2. arena + hblkhd <= INT_MAX, both are scaled down proportionally if needed
+/// 3. uordblks <= INT_MAX
+pub fn sanitize(info2: Mallinfo2C) -> MallinfoC {
+    sanitize_with_limit(info2, i32::MAX)
+}
+
+/// Same as [`sanitize`] with a lower ceiling than INT_MAX, to drive the clamping
+/// with small allocations
+pub fn sanitize_with_limit(info2: Mallinfo2C, limit: i32) -> MallinfoC {
     // Clamp arena + hblkhd
-    let mut arena = clamp_to_int_max(info2.arena as i64);
-    let mut hblkhd = clamp_to_int_max(info2.hblkhd as i64);
+    let mut arena = clamp_size(info2.arena, limit);
+    let mut hblkhd = clamp_size(info2.hblkhd, limit);
     let sum = arena as i64 + hblkhd as i64;
-    if sum > i32::MAX as i64 {
+    if sum > limit as i64 {
         // Scale both down proportionally
-        // This maintains the ratio while ensuring sum <= INT_MAX
-        let scale_factor = (i32::MAX as f64) / (sum as f64);
+        // This maintains the ratio while ensuring sum <= limit
+        let scale_factor = (limit as f64) / (sum as f64);
         arena = (arena as f64 * scale_factor) as i32;
         hblkhd = (hblkhd as f64 * scale_factor) as i32;
-
-        if !LOGGED_ONCE_ARENA_HBLKHD.swap(true, Ordering::Relaxed) {
-            warn!(
-                arena = arena,
-                hblkhd = hblkhd,
-                sum = arena as i64 + hblkhd as i64,
-                "arena + hblkhd > INT_MAX after clamping, scaling proportionally to prevent crash"
-            );
-        }
-    }
-
-    // Clamp uordblks
-    let uordblks_i64 = info2.uordblks as i64;
-    if uordblks_i64 > i32::MAX as i64 {
-        if !LOGGED_ONCE_UORDBLKS.swap(true, Ordering::Relaxed) {
-            warn!(
-                uordblks = uordblks_i64,
-                "uordblks > INT_MAX, clamping to prevent crash"
-            );
-        }
     }
-    let uordblks = clamp_to_int_max(uordblks_i64);
 
     // Still clamp the other values for safety, though they aren't used by CEF
     MallinfoC {
         arena,
-        ordblks: clamp_to_int_max(info2.ordblks as i64),
-        smblks: clamp_to_int_max(info2.smblks as i64),
-        hblks: clamp_to_int_max(info2.hblks as i64),
+        ordblks: clamp_size(info2.ordblks, limit),
+        smblks: clamp_size(info2.smblks, limit),
+        hblks: clamp_size(info2.hblks, limit),
         hblkhd,
-        usmblks: clamp_to_int_max(info2.usmblks as i64),
-        fsmblks: clamp_to_int_max(info2.fsmblks as i64),
-        uordblks,
-        fordblks: clamp_to_int_max(info2.fordblks as i64),
-        keepcost: clamp_to_int_max(info2.keepcost as i64),
+        usmblks: clamp_size(info2.usmblks, limit),
+        fsmblks: clamp_size(info2.fsmblks, limit),
+        uordblks: clamp_size(info2.uordblks, limit),
+        fordblks: clamp_size(info2.fordblks, limit),
+        keepcost: clamp_size(info2.keepcost, limit),
     }
 }
 
-/// Constructor called when library is loaded
-#[cfg(target_os = "linux")]
-#[link_section = ".init_array"]
-#[used]
-static INIT: extern "C" fn() = init;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info2(arena: usize, hblkhd: usize, uordblks: usize) -> Mallinfo2C {
+        Mallinfo2C {
+            arena,
+            ordblks: 0,
+            smblks: 0,
+            hblks: 0,
+            hblkhd,
+            usmblks: 0,
+            fsmblks: 0,
+            uordblks,
+            fordblks: 0,
+            keepcost: 0,
+        }
+    }
+
+    #[test]
+    fn no_field_is_negative() {
+        let info = sanitize(Mallinfo2C {
+            ordblks: usize::MAX,
+            keepcost: 1 << 40,
+            ..info2(usize::MAX, usize::MAX, usize::MAX)
+        });
+        let fields = [
+            info.arena,
+            info.ordblks,
+            info.smblks,
+            info.hblks,
+            info.hblkhd,
+            info.usmblks,
+            info.fsmblks,
+            info.uordblks,
+            info.fordblks,
+            info.keepcost,
+        ];
+        assert!(fields.iter().all(|field| *field >= 0));
+        assert_eq!(info.ordblks, i32::MAX);
+    }
 
-#[cfg(target_os = "linux")]
-extern "C" fn init() {
-    let _ = tracing_subscriber::fmt()
-        .with_target(true)
-        .with_thread_ids(true)
-        .try_init();
+    #[test]
+    fn arena_plus_hblkhd_fits_in_int_max() {
+        let info = sanitize(info2(176_197_632, 2_097_156_096, 0));
+        assert!(info.arena as i64 + info.hblkhd as i64 <= i32::MAX as i64);
+        // Scaled proportionally, hblkhd stays the larger one
+        assert!(info.hblkhd > info.arena);
 
-    debug!("mallinfo-override loaded: using mallinfo2() with overflow protection");
+        let info = sanitize(info2(1 << 40, 1 << 40, 0));
+        assert!(info.arena as i64 + info.hblkhd as i64 <= i32::MAX as i64);
+    }
+
+    #[test]
+    fn uordblks_fits_in_int_max() {
+        assert_eq!(sanitize(info2(0, 0, 3 << 30)).uordblks, i32::MAX);
+        assert_eq!(sanitize(info2(0, 0, 1234)).uordblks, 1234);
+    }
+
+    #[test]
+    fn values_below_the_limit_are_unchanged() {
+        let info = sanitize_with_limit(info2(100, 200, 300), 1000);
+        assert_eq!((info.arena, info.hblkhd, info.uordblks), (100, 200, 300));
+
+        let info = sanitize_with_limit(info2(600, 600, 2000), 1000);
+        assert!(info.arena + info.hblkhd <= 1000);
+        assert_eq!(info.uordblks, 1000);
+    }
 }
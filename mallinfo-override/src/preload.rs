@@ -0,0 +1,142 @@
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::OnceLock;
+use tracing::{debug, info, warn};
+
+use crate::{clamp_size, sanitize_with_limit, Mallinfo2C, MallinfoC};
+
+type Mallinfo2Fn = unsafe extern "C" fn() -> Mallinfo2C;
+type MallinfoFn = unsafe extern "C" fn() -> MallinfoC;
+
+/// glibc's mallinfo2(), resolved at runtime: linking against it would keep the
+/// library from loading at all before glibc 2.33
+fn glibc_mallinfo2() -> Option<Mallinfo2Fn> {
+    static MALLINFO2: OnceLock<Option<Mallinfo2Fn>> = OnceLock::new();
+    *MALLINFO2.get_or_init(|| unsafe {
+        let symbol = libc::dlsym(libc::RTLD_DEFAULT, c"mallinfo2".as_ptr());
+        (!symbol.is_null()).then(|| std::mem::transmute::<*mut libc::c_void, Mallinfo2Fn>(symbol))
+    })
+}
+
+/// glibc's own mallinfo(), the next definition after this library's
+fn glibc_mallinfo() -> Option<MallinfoFn> {
+    static MALLINFO: OnceLock<Option<MallinfoFn>> = OnceLock::new();
+    *MALLINFO.get_or_init(|| unsafe {
+        let symbol = libc::dlsym(libc::RTLD_NEXT, c"mallinfo".as_ptr());
+        (!symbol.is_null()).then(|| std::mem::transmute::<*mut libc::c_void, MallinfoFn>(symbol))
+    })
+}
+
+/// Allocator statistics in the mallinfo2() layout
+///
+/// Without mallinfo2() the wrapped mallinfo() fields are read as unsigned, which is
+/// exact up to 4 GiB per field.
+fn read_mallinfo2() -> Mallinfo2C {
+    if let Some(mallinfo2) = glibc_mallinfo2() {
+        return unsafe { mallinfo2() };
+    }
+    let info = match glibc_mallinfo() {
+        Some(mallinfo) => unsafe { mallinfo() },
+        None => return unsafe { std::mem::zeroed() },
+    };
+    Mallinfo2C {
+        arena: info.arena as u32 as usize,
+        ordblks: info.ordblks as u32 as usize,
+        smblks: info.smblks as u32 as usize,
+        hblks: info.hblks as u32 as usize,
+        hblkhd: info.hblkhd as u32 as usize,
+        usmblks: info.usmblks as u32 as usize,
+        fsmblks: info.fsmblks as u32 as usize,
+        uordblks: info.uordblks as u32 as usize,
+        fordblks: info.fordblks as u32 as usize,
+        keepcost: info.keepcost as u32 as usize,
+    }
+}
+
+/// Ceiling the values are clamped to, INT_MAX unless `MALLINFO_OVERRIDE_LIMIT` is set
+static LIMIT: AtomicI32 = AtomicI32::new(i32::MAX);
+
+/// Override mallinfo()
+#[no_mangle]
+pub extern "C" fn mallinfo() -> MallinfoC {
+    static LOGGED_ONCE_ARENA_HBLKHD: AtomicBool = AtomicBool::new(false);
+    static LOGGED_ONCE_UORDBLKS: AtomicBool = AtomicBool::new(false);
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let info2 = read_mallinfo2();
+    let info = sanitize_with_limit(info2, limit);
+
+    let clamped_sum =
+        clamp_size(info2.arena, limit) as i64 + clamp_size(info2.hblkhd, limit) as i64;
+    if clamped_sum > limit as i64 && !LOGGED_ONCE_ARENA_HBLKHD.swap(true, Ordering::Relaxed) {
+        warn!(
+            arena = info.arena,
+            hblkhd = info.hblkhd,
+            sum = info.arena as i64 + info.hblkhd as i64,
+            limit = limit,
+            "arena + hblkhd > limit after clamping, scaling proportionally to prevent crash"
+        );
+    }
+
+    let uordblks_i64 = i64::try_from(info2.uordblks).unwrap_or(i64::MAX);
+    if uordblks_i64 > limit as i64 && !LOGGED_ONCE_UORDBLKS.swap(true, Ordering::Relaxed) {
+        warn!(
+            uordblks = uordblks_i64,
+            limit = limit,
+            "uordblks > limit, clamping to prevent crash"
+        );
+    }
+
+    info
+}
+
+/// Reads `MALLINFO_OVERRIDE_LIMIT`, a positive ceiling below INT_MAX used for testing
+fn load_limit() {
+    let Ok(value) = std::env::var("MALLINFO_OVERRIDE_LIMIT") else {
+        return;
+    };
+    match value.trim().parse::<i32>() {
+        Ok(limit) if limit > 0 => {
+            LIMIT.store(limit, Ordering::Relaxed);
+            info!(
+                limit = limit,
+                "Clamping mallinfo() to MALLINFO_OVERRIDE_LIMIT instead of INT_MAX"
+            );
+        }
+        _ => warn!(
+            value = %value,
+            "Ignoring invalid MALLINFO_OVERRIDE_LIMIT, expected a positive 32-bit integer"
+        ),
+    }
+}
+
+/// Constructor called when library is loaded
+#[cfg(target_os = "linux")]
+#[link_section = ".init_array"]
+#[used]
+static INIT: extern "C" fn() = init;
+
+#[cfg(target_os = "linux")]
+extern "C" fn init() {
+    let _ = tracing_subscriber::fmt()
+        .with_target(true)
+        .with_thread_ids(true)
+        .try_init();
+
+    debug!("mallinfo-override loaded: clamping mallinfo() with overflow protection");
+    log_glibc_version();
+    load_limit();
+}
+
+#[cfg(target_os = "linux")]
+fn log_glibc_version() {
+    let version = unsafe { CStr::from_ptr(libc::gnu_get_libc_version()) }.to_string_lossy();
+    info!(glibc = %version, "Detected glibc version");
+
+    // mallinfo2() only exists since glibc 2.33
+    if glibc_mallinfo2().is_none() {
+        warn!(
+            glibc = %version,
+            "mallinfo2() is unavailable, falling back to mallinfo() read as unsigned (exact up to 4 GiB)"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use tracing::{info, warn};
+
+/// Parses a core list such as "0,2-3" into [0, 2, 3]
+pub fn parse_core_list(input: &str) -> Result<Vec<usize>> {
+    let max_cores = libc::CPU_SETSIZE as usize;
+    let mut cores = Vec::new();
+    for part in input.split(',') {
+        let part = part.trim();
+        // Checked before expanding a range, so "0-99999999999" can't allocate a huge list
+        let parse = |value: &str| {
+            let core = value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| anyhow!("Invalid core list '{}': bad core '{}'", input, value))?;
+            if core >= max_cores {
+                return Err(anyhow!("Invalid core list '{}': core {} is out of range", input, core));
+            }
+            Ok(core)
+        };
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(anyhow!("Invalid core list '{}': empty range {}", input, part));
+                }
+                cores.extend(start..=end);
+            }
+            None => cores.push(parse(part)?),
+        }
+    }
+
+    cores.sort_unstable();
+    cores.dedup();
+    Ok(cores)
+}
+
+/// Pins the calling thread to the given cores and logs the resulting affinity
+pub fn pin_current_thread(cores: &[usize], thread_name: &str) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            warn!(
+                "Failed to pin {} thread to cores {:?}: {}",
+                thread_name,
+                cores,
+                std::io::Error::last_os_error()
+            );
+            return;
+        }
+
+        let mut applied: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut applied) == 0 {
+            let applied: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+                .filter(|core| libc::CPU_ISSET(*core, &applied))
+                .collect();
+            info!("Pinned {} thread to cores {:?}", thread_name, applied);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_core_list_ranges_and_lists() {
+        assert_eq!(parse_core_list("3").unwrap(), vec![3]);
+        assert_eq!(parse_core_list("0-3").unwrap(), vec![0, 1, 2, 3]);
+        assert_eq!(parse_core_list("0,2-3").unwrap(), vec![0, 2, 3]);
+        assert_eq!(parse_core_list("5,1,3-4,1").unwrap(), vec![1, 3, 4, 5]);
+        assert_eq!(parse_core_list("2-2").unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn parse_core_list_whitespace() {
+        assert_eq!(parse_core_list(" 0 , 2 - 3 ").unwrap(), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn parse_core_list_invalid() {
+        assert!(parse_core_list("").is_err());
+        assert!(parse_core_list("0,").is_err());
+        assert!(parse_core_list("a").is_err());
+        assert!(parse_core_list("1-").is_err());
+        assert!(parse_core_list("-1").is_err());
+        assert!(parse_core_list("3-1").is_err());
+    }
+
+    #[test]
+    fn parse_core_list_out_of_range() {
+        let max_cores = libc::CPU_SETSIZE as usize;
+        assert_eq!(parse_core_list(&(max_cores - 1).to_string()).unwrap(), vec![max_cores - 1]);
+        assert!(parse_core_list(&max_cores.to_string()).is_err());
+        assert!(parse_core_list("0-99999999999").is_err());
+    }
+}
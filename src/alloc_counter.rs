@@ -0,0 +1,193 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, UnsafeCell};
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use tracing::info;
+
+use crate::memory_monitor;
+
+/// Counts Rust heap allocations per thread name
+///
+/// Only allocations made through the Rust global allocator are seen here: CEF and the
+/// other native libraries call malloc directly and only show up in mallinfo.
+/// Frees are attributed to the thread that frees, so the net bytes of a thread can be
+/// negative when memory is handed over to another thread.
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const MAX_THREAD_SLOTS: usize = 64;
+/// Linux limits thread names to 15 bytes plus the nul terminator
+const NAME_LEN: usize = 16;
+const UNASSIGNED: usize = usize::MAX;
+/// Number of threads listed in the periodic report
+const REPORT_TOP: usize = 5;
+
+const SLOT_FREE: u8 = 0;
+const SLOT_CLAIMING: u8 = 1;
+const SLOT_READY: u8 = 2;
+
+struct ThreadSlot {
+    state: AtomicU8,
+    name: UnsafeCell<[u8; NAME_LEN]>,
+    name_len: AtomicUsize,
+    allocated: AtomicU64,
+    freed: AtomicU64,
+}
+
+// The name is only written by the thread that claimed the slot, before it is marked ready
+unsafe impl Sync for ThreadSlot {}
+
+impl ThreadSlot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(SLOT_FREE),
+            name: UnsafeCell::new([0; NAME_LEN]),
+            name_len: AtomicUsize::new(0),
+            allocated: AtomicU64::new(0),
+            freed: AtomicU64::new(0),
+        }
+    }
+
+    fn name(&self) -> &[u8] {
+        let len = self.name_len.load(Ordering::Acquire);
+        unsafe { &(&*self.name.get())[..len] }
+    }
+}
+
+static SLOTS: [ThreadSlot; MAX_THREAD_SLOTS] = [const { ThreadSlot::new() }; MAX_THREAD_SLOTS];
+static TOTAL_ALLOCATED: AtomicU64 = AtomicU64::new(0);
+static TOTAL_FREED: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD_SLOT: Cell<usize> = const { Cell::new(UNASSIGNED) };
+}
+
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size() as u64, 0);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record(layout.size() as u64, 0);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(0, layout.size() as u64);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(new_size as u64, layout.size() as u64);
+        }
+        new_ptr
+    }
+}
+
+/// Must not allocate: it runs inside the global allocator
+fn record(allocated: u64, freed: u64) {
+    TOTAL_ALLOCATED.fetch_add(allocated, Ordering::Relaxed);
+    TOTAL_FREED.fetch_add(freed, Ordering::Relaxed);
+
+    let slot = THREAD_SLOT
+        .try_with(|slot| {
+            if slot.get() == UNASSIGNED {
+                slot.set(find_or_claim_slot());
+            }
+            slot.get()
+        })
+        .unwrap_or(UNASSIGNED);
+    if let Some(slot) = SLOTS.get(slot) {
+        slot.allocated.fetch_add(allocated, Ordering::Relaxed);
+        slot.freed.fetch_add(freed, Ordering::Relaxed);
+    }
+}
+
+/// Returns the slot for the current thread name, claiming a new one if needed.
+/// Threads sharing a name share a slot. Returns UNASSIGNED when the table is full.
+fn find_or_claim_slot() -> usize {
+    let mut name = [0u8; NAME_LEN];
+    let len = unsafe {
+        let ret = libc::pthread_getname_np(
+            libc::pthread_self(),
+            name.as_mut_ptr() as *mut libc::c_char,
+            NAME_LEN,
+        );
+        if ret == 0 {
+            name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN)
+        } else {
+            0
+        }
+    };
+    let name = &name[..len];
+
+    for (index, slot) in SLOTS.iter().enumerate() {
+        if slot.state.load(Ordering::Acquire) == SLOT_READY && slot.name() == name {
+            return index;
+        }
+    }
+    for (index, slot) in SLOTS.iter().enumerate() {
+        if slot
+            .state
+            .compare_exchange(SLOT_FREE, SLOT_CLAIMING, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { (&mut *slot.name.get())[..len].copy_from_slice(name) };
+            slot.name_len.store(len, Ordering::Release);
+            slot.state.store(SLOT_READY, Ordering::Release);
+            return index;
+        }
+    }
+    UNASSIGNED
+}
+
+/// Logs the Rust heap totals and the threads holding the most net bytes
+pub fn log_report() {
+    let allocated = TOTAL_ALLOCATED.load(Ordering::Relaxed);
+    let freed = TOTAL_FREED.load(Ordering::Relaxed);
+
+    let mut threads: Vec<(String, i64)> = SLOTS
+        .iter()
+        .filter(|slot| slot.state.load(Ordering::Acquire) == SLOT_READY)
+        .map(|slot| {
+            let name = match String::from_utf8_lossy(slot.name()) {
+                name if name.is_empty() => "<unnamed>".to_string(),
+                name => name.into_owned(),
+            };
+            let net = slot.allocated.load(Ordering::Relaxed) as i64 - slot.freed.load(Ordering::Relaxed) as i64;
+            (name, net)
+        })
+        .collect();
+    threads.sort_by_key(|(_, net)| std::cmp::Reverse(*net));
+    let live = allocated as i64 - freed as i64;
+
+    if memory_monitor::structured_logs() {
+        info!(allocated, freed, live, "Rust heap");
+        for (name, net) in threads.iter().take(REPORT_TOP) {
+            info!(thread = name.as_str(), net_bytes = *net, "Rust heap by thread");
+        }
+    } else {
+        let top: Vec<String> = threads
+            .iter()
+            .take(REPORT_TOP)
+            .map(|(name, net)| format!("{}: {}", name, memory_monitor::signed_human_bytes(*net)))
+            .collect();
+        info!(
+            "Rust heap: allocated {}, freed {}, live {}\n  top threads by net bytes:\n    {}",
+            memory_monitor::human_bytes(allocated as i64),
+            memory_monitor::human_bytes(freed as i64),
+            memory_monitor::human_bytes(live),
+            top.join("\n    ")
+        );
+    }
+}
@@ -1,30 +1,491 @@
 use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+
+use crate::error::SmelterCrashError;
 
 #[derive(Debug)]
 pub struct Args {
+    /// `list-gpus` subcommand: print the available adapters and exit
+    pub list_gpus: bool,
+    pub config: Option<PathBuf>,
     pub allocate_ram: Option<String>,
+    pub baseline: Option<PathBuf>,
+    pub shm: Option<String>,
+    pub detailed_mem: bool,
+    pub raw_mallinfo2: bool,
+    pub consume_rate: Option<String>,
+    pub snapshot: Option<PathBuf>,
+    pub bench: Option<String>,
+    pub max_runtime: Option<String>,
+    pub events: Option<PathBuf>,
+    pub pin_cpu: Option<String>,
+    pub quiet: bool,
+    pub rescale: Option<String>,
+    pub align: Option<String>,
+    pub bg: Option<String>,
+    pub detect_stall_frames: bool,
+    pub monitor_warmup: Option<String>,
+    pub fail_on_overflow: bool,
+    pub check_fields: Option<String>,
+    pub ignore_fields: Option<String>,
+    pub profile: Option<PathBuf>,
+    pub consumers: Option<String>,
+    pub html: Option<PathBuf>,
+    pub web_mem: bool,
+    pub border: Option<String>,
+    pub shadow: bool,
+    pub start_delay: Option<String>,
+    pub thread_stack: Option<String>,
+    pub tcp_out: Option<String>,
+    pub snapshot_history: Option<String>,
+    pub color: Option<String>,
+    pub json_logs: bool,
+    pub human: bool,
+    pub resource_counts: bool,
+    pub web_gpu: bool,
+    pub timeline: Option<PathBuf>,
+    pub hash_frames: Option<String>,
+    pub hash_frames_out: Option<PathBuf>,
+    pub renderer_churn: Option<String>,
+    pub show_pts: Option<String>,
+    pub gpu_init_retries: Option<String>,
+    pub backend: Option<String>,
+    pub animate_opacity: bool,
+    pub max_frames: Option<String>,
+    pub statsd: Option<String>,
+    pub smooth: Option<String>,
+    pub force_ram: bool,
+    pub release_ram_on_sigusr1: bool,
+    pub ram_max_percent: Option<String>,
+    pub transition: Option<String>,
+    pub dump_maps_interval: Option<String>,
+    pub dump_maps_dir: Option<PathBuf>,
+    pub dump_smaps: bool,
+    pub output_id: Option<String>,
+    pub require_override: bool,
 }
 
 impl Args {
-    pub fn parse() -> Result<Self> {
-        let args: Vec<String> = std::env::args().collect();
+    pub fn parse() -> Result<Self, SmelterCrashError> {
+        Self::parse_from(std::env::args().collect()).map_err(SmelterCrashError::ArgParse)
+    }
 
+    fn parse_from(args: Vec<String>) -> Result<Self> {
+        let mut config: Option<PathBuf> = None;
         let mut allocate_ram: Option<String> = None;
+        let mut baseline: Option<PathBuf> = None;
+        let mut shm: Option<String> = None;
+        let mut detailed_mem = false;
+        let mut raw_mallinfo2 = false;
+        let mut consume_rate: Option<String> = None;
+        let mut snapshot: Option<PathBuf> = None;
+        let mut bench: Option<String> = None;
+        let mut max_runtime: Option<String> = None;
+        let mut events: Option<PathBuf> = None;
+        let mut pin_cpu: Option<String> = None;
+        let mut quiet = false;
+        let mut rescale: Option<String> = None;
+        let mut align: Option<String> = None;
+        let mut bg: Option<String> = None;
+        let mut detect_stall_frames = false;
+        let mut monitor_warmup: Option<String> = None;
+        let mut fail_on_overflow = false;
+        let mut check_fields: Option<String> = None;
+        let mut ignore_fields: Option<String> = None;
+        let mut profile: Option<PathBuf> = None;
+        let mut consumers: Option<String> = None;
+        let mut html: Option<PathBuf> = None;
+        let mut web_mem = false;
+        let mut border: Option<String> = None;
+        let mut shadow = false;
+        let mut start_delay: Option<String> = None;
+        let mut thread_stack: Option<String> = None;
+        let mut tcp_out: Option<String> = None;
+        let mut snapshot_history: Option<String> = None;
+        let mut color: Option<String> = None;
+        let mut json_logs = false;
+        let mut human = false;
+        let mut resource_counts = false;
+        let mut web_gpu = false;
+        let mut timeline: Option<PathBuf> = None;
+        let mut hash_frames: Option<String> = None;
+        let mut hash_frames_out: Option<PathBuf> = None;
+        let mut renderer_churn: Option<String> = None;
+        let mut show_pts: Option<String> = None;
+        let mut gpu_init_retries: Option<String> = None;
+        let mut backend: Option<String> = None;
+        let mut animate_opacity = false;
+        let mut max_frames: Option<String> = None;
+        let mut statsd: Option<String> = None;
+        let mut smooth: Option<String> = None;
+        let mut force_ram = false;
+        let mut release_ram_on_sigusr1 = false;
+        let mut ram_max_percent: Option<String> = None;
+        let mut transition: Option<String> = None;
+        let mut dump_maps_interval: Option<String> = None;
+        let mut dump_maps_dir: Option<PathBuf> = None;
+        let mut dump_smaps = false;
+        let mut output_id: Option<String> = None;
+        let mut require_override = false;
 
         let mut i = 1;
+        let list_gpus = args.get(1).is_some_and(|arg| arg == "list-gpus");
+        if list_gpus {
+            i += 1;
+        }
         while i < args.len() {
             let arg = &args[i];
-            if arg == "--ram" {
+            if arg == "--config" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--config requires a path (e.g., repro.toml)"));
+                }
+                config = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--ram" {
                 if i + 1 >= args.len() {
                     return Err(anyhow!("--ram requires a value (e.g., 100M, 2G)"));
                 }
                 allocate_ram = Some(args[i + 1].clone());
                 i += 2;
+            } else if arg == "--baseline" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--baseline requires a path (e.g., baseline.json)"));
+                }
+                baseline = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--shm" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--shm requires a name (e.g., /smelter_frames)"));
+                }
+                shm = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--detailed-mem" {
+                detailed_mem = true;
+                i += 1;
+            } else if arg == "--raw-mallinfo2" {
+                raw_mallinfo2 = true;
+                i += 1;
+            } else if arg == "--consume-rate" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--consume-rate requires a value in fps (e.g., 15)"));
+                }
+                consume_rate = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--snapshot" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--snapshot requires a path (e.g., snapshot.png)"));
+                }
+                snapshot = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--bench" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--bench requires a duration (e.g., 30s, 5m)"));
+                }
+                bench = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--max-runtime" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--max-runtime requires a duration (e.g., 2h)"));
+                }
+                max_runtime = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--events" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--events requires a path (e.g., events.ndjson)"));
+                }
+                events = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--pin-cpu" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--pin-cpu requires a core list (e.g., 0,2-3)"));
+                }
+                pin_cpu = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--quiet" {
+                quiet = true;
+                i += 1;
+            } else if arg == "--rescale" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--rescale requires a mode (fit or fill)"));
+                }
+                rescale = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--align" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--align requires a value (e.g., center, left:top)"));
+                }
+                align = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--bg" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--bg requires a color (e.g., FF00FF, 00000080)"));
+                }
+                bg = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--detect-stall-frames" {
+                detect_stall_frames = true;
+                i += 1;
+            } else if arg == "--monitor-warmup" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--monitor-warmup requires a duration (e.g., 0, 500ms, 5s)"));
+                }
+                monitor_warmup = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--fail-on-overflow" {
+                fail_on_overflow = true;
+                i += 1;
+            } else if arg == "--check-fields" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--check-fields requires a field list (e.g., arena,uordblks)"));
+                }
+                check_fields = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--ignore-fields" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--ignore-fields requires a field list (e.g., fordblks)"));
+                }
+                ignore_fields = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--profile" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--profile requires a path (e.g., dhat-heap.json)"));
+                }
+                profile = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--consumers" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--consumers requires a thread count (e.g., 4)"));
+                }
+                consumers = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--html" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--html requires a path (e.g., page.html)"));
+                }
+                html = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--web-mem" {
+                web_mem = true;
+                i += 1;
+            } else if arg == "--border" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--border requires a width and color (e.g., 8:FF0000)"));
+                }
+                border = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--shadow" {
+                shadow = true;
+                i += 1;
+            } else if arg == "--start-delay" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--start-delay requires a duration (e.g., 500ms, 2s)"));
+                }
+                start_delay = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--thread-stack" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--thread-stack requires a size (e.g., 8M)"));
+                }
+                thread_stack = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--tcp-out" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--tcp-out requires an address (e.g., 127.0.0.1:5000)"));
+                }
+                tcp_out = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--snapshot-history" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--snapshot-history requires a sample count (e.g., 60, 0 to disable)"));
+                }
+                snapshot_history = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--color" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--color requires a mode (auto, always or never)"));
+                }
+                color = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--json-logs" {
+                json_logs = true;
+                i += 1;
+            } else if arg == "--human" {
+                human = true;
+                i += 1;
+            } else if arg == "--resource-counts" {
+                resource_counts = true;
+                i += 1;
+            } else if arg == "--web-gpu" {
+                web_gpu = true;
+                i += 1;
+            } else if arg == "--timeline" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--timeline requires a path (e.g., timeline.txt)"));
+                }
+                timeline = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--hash-frames" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--hash-frames requires an algorithm (crc32, xxhash or blake3)"));
+                }
+                hash_frames = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--hash-frames-out" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--hash-frames-out requires a path (e.g., frame_hashes.txt)"));
+                }
+                hash_frames_out = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--renderer-churn" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--renderer-churn requires an interval (e.g., 500ms, 2s)"));
+                }
+                renderer_churn = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--show-pts" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--show-pts requires a frame interval (e.g., 30, 1 for every frame)"));
+                }
+                show_pts = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--gpu-init-retries" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--gpu-init-retries requires a retry count (e.g., 5)"));
+                }
+                gpu_init_retries = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--backend" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--backend requires a backend (vulkan, gl, metal or dx12)"));
+                }
+                backend = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--animate-opacity" {
+                animate_opacity = true;
+                i += 1;
+            } else if arg == "--max-frames" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--max-frames requires a frame count (e.g., 9000)"));
+                }
+                max_frames = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--statsd" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--statsd requires an address (e.g., 127.0.0.1:8125)"));
+                }
+                statsd = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--smooth" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--smooth requires a sample count (e.g., 5)"));
+                }
+                smooth = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--force-ram" {
+                force_ram = true;
+                i += 1;
+            } else if arg == "--release-ram-on-sigusr1" {
+                release_ram_on_sigusr1 = true;
+                i += 1;
+            } else if arg == "--ram-max-percent" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--ram-max-percent requires a percentage (e.g., 90)"));
+                }
+                ram_max_percent = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--transition" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--transition requires a duration (e.g., 500ms, 2s)"));
+                }
+                transition = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--dump-maps-interval" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--dump-maps-interval requires a duration (e.g., 30s, 5m)"));
+                }
+                dump_maps_interval = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--dump-maps-dir" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--dump-maps-dir requires a path (e.g., maps_dumps)"));
+                }
+                dump_maps_dir = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            } else if arg == "--dump-smaps" {
+                dump_smaps = true;
+                i += 1;
+            } else if arg == "--output-id" {
+                if i + 1 >= args.len() {
+                    return Err(anyhow!("--output-id requires an id (e.g., output)"));
+                }
+                output_id = Some(args[i + 1].clone());
+                i += 2;
+            } else if arg == "--require-override" {
+                require_override = true;
+                i += 1;
             } else {
                 return Err(anyhow!("Unknown argument: {}", arg));
             }
         }
 
-        Ok(Args { allocate_ram })
+        Ok(Args {
+            list_gpus,
+            config,
+            allocate_ram,
+            baseline,
+            shm,
+            detailed_mem,
+            raw_mallinfo2,
+            consume_rate,
+            snapshot,
+            bench,
+            max_runtime,
+            events,
+            pin_cpu,
+            quiet,
+            rescale,
+            align,
+            bg,
+            detect_stall_frames,
+            monitor_warmup,
+            fail_on_overflow,
+            check_fields,
+            ignore_fields,
+            profile,
+            consumers,
+            html,
+            web_mem,
+            border,
+            shadow,
+            start_delay,
+            thread_stack,
+            tcp_out,
+            snapshot_history,
+            color,
+            json_logs,
+            human,
+            resource_counts,
+            web_gpu,
+            timeline,
+            hash_frames,
+            hash_frames_out,
+            renderer_churn,
+            show_pts,
+            gpu_init_retries,
+            backend,
+            animate_opacity,
+            max_frames,
+            statsd,
+            smooth,
+            force_ram,
+            release_ram_on_sigusr1,
+            ram_max_percent,
+            transition,
+            dump_maps_interval,
+            dump_maps_dir,
+            dump_smaps,
+            output_id,
+            require_override,
+        })
     }
 }
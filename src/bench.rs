@@ -0,0 +1,116 @@
+use compositor_pipeline::Pipeline;
+use compositor_render::OutputId;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::events::{self, Event};
+use crate::output::{self, FrameStats};
+use crate::shutdown::{self, ExitReason};
+use crate::{churn, memory_monitor, threads};
+
+/// Runs the pipeline for a fixed duration, then prints a summary table and exits
+pub fn start_bench(duration: Duration, stats: Arc<FrameStats>, churn_enabled: bool) {
+    threads::spawn("bench", move || {
+        info!("Benchmark running for {:?}", duration);
+        thread::sleep(duration);
+
+        memory_monitor::sample_peaks();
+        print_report(duration, &stats, churn_enabled, ExitReason::DurationElapsed);
+        shutdown::shutdown(ExitReason::DurationElapsed);
+    });
+}
+
+/// Waits for the output to receive --max-frames frames, then unregisters it, prints
+/// the benchmark summary and exits
+pub fn start_max_frames_watch(
+    reached: mpsc::Receiver<()>,
+    pipeline: Arc<Mutex<Pipeline>>,
+    output_id: OutputId,
+    stats: Arc<FrameStats>,
+    churn_enabled: bool,
+) {
+    threads::spawn("max_frames", move || {
+        let start = Instant::now();
+        if reached.recv().is_err() {
+            return;
+        }
+        let elapsed = start.elapsed();
+        info!("Received {} frames (--max-frames), stopping", stats.frame_count());
+        output::mark_output_closing();
+        let result = pipeline.lock().unwrap().unregister_output(&output_id);
+        match result {
+            Ok(()) => events::record(Event::OutputUnregistered {
+                output_id: output_id.0.to_string(),
+            }),
+            Err(e) => warn!("Failed to unregister the output: {}", e),
+        }
+
+        memory_monitor::sample_peaks();
+        print_report(elapsed, &stats, churn_enabled, ExitReason::MaxFrames);
+        shutdown::shutdown(ExitReason::MaxFrames);
+    });
+}
+
+fn print_report(duration: Duration, stats: &FrameStats, churn_enabled: bool, reason: ExitReason) {
+    let frames = stats.frame_count();
+    let mut intervals = stats.intervals();
+    intervals.sort();
+    let peaks = memory_monitor::peaks();
+
+    println!("Benchmark results ({:?})", duration);
+    println!("  {:<16} {}", "exit reason", reason);
+    println!("  {:<16} {}", "frames", frames);
+    match stats.frame_format() {
+        Some(format) => println!(
+            "  {:<16} {}x{} {}",
+            "frame format", format.resolution.width, format.resolution.height, format.pixel_format
+        ),
+        None => println!("  {:<16} n/a", "frame format"),
+    }
+    match stats.time_to_first_frame() {
+        Some(latency) => println!("  {:<16} {:.2} ms", "first frame", as_ms(latency)),
+        None => println!("  {:<16} never", "first frame"),
+    }
+    println!("  {:<16} {:.2}", "fps", frames as f64 / duration.as_secs_f64());
+    if intervals.is_empty() {
+        println!("  {:<16} n/a", "interval");
+    } else {
+        let mean = intervals.iter().sum::<Duration>() / intervals.len() as u32;
+        println!("  {:<16} {:.2} ms", "interval mean", as_ms(mean));
+        println!("  {:<16} {:.2} ms", "interval p50", as_ms(percentile(&intervals, 50)));
+        println!("  {:<16} {:.2} ms", "interval p99", as_ms(percentile(&intervals, 99)));
+    }
+    println!(
+        "  {:<16} {:.2} MB",
+        "peak uordblks",
+        peaks.uordblks as f64 / 1_048_576.0
+    );
+    match stats.recv_errors() {
+        (0, _) => println!("  {:<16} 0", "recv errors"),
+        (count, last) => println!(
+            "  {:<16} {} (last: {})",
+            "recv errors",
+            count,
+            last.unwrap_or("n/a")
+        ),
+    }
+    match peaks.rss {
+        Some(rss) => println!("  {:<16} {:.2} MB", "peak RSS", rss as f64 / 1_048_576.0),
+        None => println!("  {:<16} n/a", "peak RSS"),
+    }
+    if churn_enabled {
+        println!("  {:<16} {}", "churn cycles", churn::cycles());
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let index = (sorted.len() * percent / 100).min(sorted.len() - 1);
+    sorted[index]
+}
+
+fn as_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,54 @@
+use compositor_pipeline::Pipeline;
+use compositor_render::{RegistryType, RendererId, Resolution};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{input, threads};
+
+/// Log the cycle count every this many cycles
+const LOG_EVERY_CYCLES: u64 = 100;
+
+static CYCLES: AtomicU64 = AtomicU64::new(0);
+
+/// Number of completed register/unregister cycles
+pub fn cycles() -> u64 {
+    CYCLES.load(Ordering::Relaxed)
+}
+
+/// Registers and unregisters an extra web renderer, waiting `interval` after each
+/// step, to churn CEF instances while the main scene keeps rendering
+pub fn start_renderer_churn(
+    pipeline: Arc<Mutex<Pipeline>>,
+    url: String,
+    resolution: Resolution,
+    interval: Duration,
+) {
+    info!("Churning a web renderer every {:?} (--renderer-churn)", interval);
+    let renderer_id = RendererId(Arc::from("churn_renderer"));
+
+    threads::spawn("renderer_churn", move || loop {
+        std::thread::sleep(interval);
+        let spec = input::web_renderer_spec(&url, resolution);
+        if let Err(e) = Pipeline::register_renderer(&pipeline, renderer_id.clone(), spec) {
+            warn!("Renderer churn: failed to register the web renderer: {:#}", e);
+            continue;
+        }
+
+        std::thread::sleep(interval);
+        let result = pipeline
+            .lock()
+            .unwrap()
+            .unregister_renderer(&renderer_id, RegistryType::WebRenderer);
+        if let Err(e) = result {
+            warn!("Renderer churn: failed to unregister the web renderer: {:#}", e);
+            continue;
+        }
+
+        let cycles = CYCLES.fetch_add(1, Ordering::Relaxed) + 1;
+        if cycles.is_multiple_of(LOG_EVERY_CYCLES) {
+            info!("Renderer churn: {} cycles", cycles);
+        }
+    });
+}
@@ -0,0 +1,656 @@
+use anyhow::{anyhow, Context, Result};
+use compositor_render::scene::RGBAColor;
+use compositor_render::{Framerate, Resolution};
+use serde::{Deserialize, Deserializer};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::affinity;
+use crate::args::Args;
+use crate::error::SmelterCrashError;
+use crate::frame_hash::{self, HashAlgorithm};
+use crate::gpus::Backend;
+use crate::input::{self, SceneOptions};
+use crate::memory_monitor::{self, MonitorOptions};
+use crate::output::{self, ConsumerOptions};
+use crate::ram;
+use crate::timeline::{self, TimelineEntry};
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+const FRAMERATE: u32 = 30;
+const WEB_URL: &str = "https://google.com";
+const DUMP_MAPS_DIR: &str = "maps_dumps";
+const ANIMATED_VIEW_COLOR: RGBAColor = RGBAColor(255, 0, 0, 255);
+/// Slowest --consume-rate accepted, one frame every ~17 minutes
+const MIN_CONSUME_RATE: f64 = 0.001;
+
+/// Validated configuration built once from the command line arguments
+#[derive(Debug)]
+pub struct Config {
+    pub resolution: Resolution,
+    pub framerate: Framerate,
+    pub web_url: String,
+    /// Let CEF render the web page on the GPU instead of in software. This is
+    /// independent from `force_gpu`, which is always off: the compositor itself may
+    /// still fall back to a software adapter.
+    pub web_gpu: bool,
+    pub scene: SceneOptions,
+    /// Amount of RAM to allocate and hold, in bytes
+    pub allocate_ram: Option<usize>,
+    /// Free the --ram allocation on the first SIGUSR1 instead of holding it forever
+    pub release_ram_on_sigusr1: bool,
+    pub monitor: MonitorOptions,
+    pub consumer: ConsumerOptions,
+    /// Run for this long, print a benchmark summary and exit
+    pub bench: Option<Duration>,
+    /// Hard cap on the process lifetime, whatever the mode
+    pub max_runtime: Option<Duration>,
+    /// Pause between starting the pipeline and registering the input and output
+    pub start_delay: Option<Duration>,
+    /// NDJSON file the lifecycle events are appended to
+    pub events_path: Option<PathBuf>,
+    /// Only log warnings and errors
+    pub quiet: bool,
+    /// Whether console logs use ANSI colors
+    pub color: ColorMode,
+    /// Log as newline-delimited JSON instead of the human readable format
+    pub json_logs: bool,
+    /// Stack size of the spawned threads, in bytes
+    pub thread_stack: Option<usize>,
+    /// Show a view of this color with an opacity animated every frame instead of the
+    /// web page
+    pub animate_opacity: Option<RGBAColor>,
+    /// Shrink the web page with a transition of this duration after the output starts
+    pub transition: Option<Duration>,
+    /// Copy /proc/self/maps to `dump_maps_dir` with this interval
+    pub dump_maps_interval: Option<Duration>,
+    pub dump_maps_dir: PathBuf,
+    /// Also copy /proc/self/smaps with the maps
+    pub dump_smaps: bool,
+    /// Exit at startup if libmallinfo_override isn't preloaded
+    pub require_override: bool,
+    /// Extra attempts at creating the graphics context before giving up
+    pub gpu_init_retries: u32,
+    /// wgpu backend the graphics context has to use
+    pub backend: Option<Backend>,
+    /// Register and unregister an extra web renderer with this interval
+    pub renderer_churn: Option<Duration>,
+    /// Scene changes applied while running, sorted by offset
+    pub timeline: Vec<TimelineEntry>,
+    /// Where the dhat heap profile is written (requires the `dhat-heap` feature)
+    pub profile_path: Option<PathBuf>,
+}
+
+/// When to colorize the console logs
+#[derive(Debug, Clone, Copy)]
+pub enum ColorMode {
+    /// Only when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn parse(input: &str) -> Result<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            other => Err(anyhow!("Invalid color mode: '{}'. Use auto, always or never", other)),
+        }
+    }
+
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Auto => std::io::stdout().is_terminal(),
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+/// Options loaded from a `--config` TOML file
+///
+/// Keys are the command line flag names without the leading dashes, and accept
+/// the same human readable values (e.g. `ram = "2G"`).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    #[serde(deserialize_with = "deserialize_memory_size")]
+    ram: Option<usize>,
+    baseline: Option<PathBuf>,
+    shm: Option<String>,
+    detailed_mem: Option<bool>,
+    raw_mallinfo2: Option<bool>,
+    consume_rate: Option<f64>,
+    snapshot: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    bench: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    max_runtime: Option<Duration>,
+    events: Option<PathBuf>,
+    pin_cpu: Option<String>,
+    quiet: Option<bool>,
+    rescale: Option<String>,
+    align: Option<String>,
+    bg: Option<String>,
+    detect_stall_frames: Option<bool>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    monitor_warmup: Option<Duration>,
+    fail_on_overflow: Option<bool>,
+    check_fields: Option<String>,
+    ignore_fields: Option<String>,
+    profile: Option<PathBuf>,
+    consumers: Option<usize>,
+    html: Option<PathBuf>,
+    web_mem: Option<bool>,
+    border: Option<String>,
+    shadow: Option<bool>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    start_delay: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_memory_size")]
+    thread_stack: Option<usize>,
+    tcp_out: Option<String>,
+    snapshot_history: Option<usize>,
+    color: Option<String>,
+    json_logs: Option<bool>,
+    human: Option<bool>,
+    resource_counts: Option<bool>,
+    web_gpu: Option<bool>,
+    timeline: Option<PathBuf>,
+    hash_frames: Option<String>,
+    hash_frames_out: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    renderer_churn: Option<Duration>,
+    show_pts: Option<u64>,
+    gpu_init_retries: Option<u32>,
+    backend: Option<String>,
+    animate_opacity: Option<bool>,
+    max_frames: Option<u64>,
+    statsd: Option<String>,
+    smooth: Option<usize>,
+    force_ram: Option<bool>,
+    release_ram_on_sigusr1: Option<bool>,
+    ram_max_percent: Option<f64>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    transition: Option<Duration>,
+    #[serde(deserialize_with = "deserialize_duration")]
+    dump_maps_interval: Option<Duration>,
+    dump_maps_dir: Option<PathBuf>,
+    dump_smaps: Option<bool>,
+    output_id: Option<String>,
+    require_override: Option<bool>,
+}
+
+impl ConfigFile {
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        // The toml error already points at the offending line and column
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))
+    }
+}
+
+fn deserialize_memory_size<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    ram::parse_memory_size(&value)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse_duration(&value).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Parses a duration made of `<number><unit>` parts, e.g. "500ms", "5s", "10m" or "1h30m"
+///
+/// Units are `h`, `m`, `s` and `ms`, each used at most once and in that order
+/// ("1h30m" is valid, "5s5s" and "30m1h" are not). A bare number is a number of seconds.
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("Invalid duration format: empty"));
+    }
+    if let Ok(secs) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut previous_unit: Option<&str> = None;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let num_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let (num_str, after_num) = rest.split_at(num_end);
+        if num_str.is_empty() {
+            return Err(anyhow!("Invalid duration format '{}': missing number", input));
+        }
+        let unit_end = after_num.find(|c: char| c.is_ascii_digit()).unwrap_or(after_num.len());
+        let (unit_str, after_unit) = after_num.split_at(unit_end);
+
+        let num: u64 = num_str
+            .parse()
+            .map_err(|_| anyhow!("Failed to parse number: {}", num_str))?;
+        let unit = unit_str.trim();
+        let secs_multiplier = match unit {
+            "ms" => None,
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(3600),
+            _ => return Err(anyhow!("Invalid duration unit: '{}'. Use ms, s, m or h", unit_str)),
+        };
+        if let Some(previous) = previous_unit {
+            if previous == unit {
+                return Err(anyhow!("Invalid duration '{}': unit '{}' is repeated", input, unit));
+            }
+            if unit_rank(unit) < unit_rank(previous) {
+                return Err(anyhow!(
+                    "Invalid duration '{}': '{}' must come before '{}'",
+                    input,
+                    unit,
+                    previous
+                ));
+            }
+        }
+        previous_unit = Some(unit);
+        let part = match secs_multiplier {
+            None => Some(Duration::from_millis(num)),
+            Some(multiplier) => num.checked_mul(multiplier).map(Duration::from_secs),
+        };
+        total = part
+            .and_then(|part| total.checked_add(part))
+            .ok_or_else(|| anyhow!("Duration too large: {}", input))?;
+
+        rest = after_unit;
+    }
+
+    Ok(total)
+}
+
+/// Position of a (valid) unit in the canonical h, m, s, ms order
+fn unit_rank(unit: &str) -> u8 {
+    match unit {
+        "h" => 0,
+        "m" => 1,
+        "s" => 2,
+        _ => 3,
+    }
+}
+
+impl TryFrom<Args> for Config {
+    type Error = SmelterCrashError;
+
+    fn try_from(args: Args) -> Result<Self, SmelterCrashError> {
+        Self::from_args(args).map_err(SmelterCrashError::ArgParse)
+    }
+}
+
+impl Config {
+    /// Command line flags override the values from the `--config` file
+    fn from_args(args: Args) -> Result<Self> {
+        let file = match &args.config {
+            Some(path) => ConfigFile::load(path)?,
+            None => ConfigFile::default(),
+        };
+
+        let allocate_ram = match args.allocate_ram.as_deref() {
+            Some(size) => Some(ram::parse_memory_size(size)?),
+            None => file.ram,
+        };
+        let force_ram = args.force_ram || file.force_ram.unwrap_or(false);
+        if let (Some(bytes), false) = (allocate_ram, force_ram) {
+            let max_percent = match args.ram_max_percent {
+                Some(value) => value
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid --ram-max-percent value: {}", value))?,
+                None => file.ram_max_percent.unwrap_or(ram::DEFAULT_MAX_PERCENT),
+            };
+            if !max_percent.is_finite() || max_percent <= 0.0 {
+                return Err(anyhow!("--ram-max-percent must be a positive number: {}", max_percent));
+            }
+            ram::check_physical_memory(bytes, max_percent)?;
+        }
+
+        let shm = args.shm.or(file.shm);
+        if let Some(name) = &shm {
+            if !name.starts_with('/') {
+                return Err(anyhow!("--shm name must start with '/': {}", name));
+            }
+        }
+
+        let consume_rate = match args.consume_rate {
+            Some(value) => Some(
+                value
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid --consume-rate value: {}", value))?,
+            ),
+            None => file.consume_rate,
+        };
+        if let Some(rate) = consume_rate {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(anyhow!("--consume-rate must be a positive number: {}", rate));
+            }
+            if rate < MIN_CONSUME_RATE {
+                return Err(anyhow!(
+                    "--consume-rate must be at least {} fps: {}",
+                    MIN_CONSUME_RATE,
+                    rate
+                ));
+            }
+        }
+
+        let consumers = match args.consumers {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| anyhow!("Invalid --consumers value: {}", value))?,
+            None => file.consumers.unwrap_or(1),
+        };
+        if consumers == 0 {
+            return Err(anyhow!("--consumers must be at least 1"));
+        }
+
+        let web_url = match args.html.or(file.html) {
+            Some(path) => input::html_file_url(&path)?,
+            None => WEB_URL.to_string(),
+        };
+
+        let bench = match args.bench.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.bench,
+        };
+
+        let max_runtime = match args.max_runtime.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.max_runtime,
+        };
+
+        let thread_stack = match args.thread_stack.as_deref() {
+            Some(size) => Some(ram::parse_memory_size(size)?),
+            None => file.thread_stack,
+        };
+
+        let hash_frames = match args.hash_frames.or(file.hash_frames) {
+            Some(algorithm) => Some((
+                HashAlgorithm::parse(&algorithm)?,
+                args.hash_frames_out
+                    .or(file.hash_frames_out)
+                    .unwrap_or_else(|| PathBuf::from(frame_hash::DEFAULT_HASH_PATH)),
+            )),
+            None => None,
+        };
+
+        let show_pts = match args.show_pts {
+            Some(value) => Some(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid --show-pts value: {}", value))?,
+            ),
+            None => file.show_pts,
+        };
+        if show_pts == Some(0) {
+            return Err(anyhow!("--show-pts must be at least 1"));
+        }
+
+        let max_frames = match args.max_frames {
+            Some(value) => Some(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid --max-frames value: {}", value))?,
+            ),
+            None => file.max_frames,
+        };
+        if max_frames == Some(0) {
+            return Err(anyhow!("--max-frames must be at least 1"));
+        }
+
+        let smooth = match args.smooth {
+            Some(value) => Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Invalid --smooth value: {}", value))?,
+            ),
+            None => file.smooth,
+        };
+        if smooth == Some(0) {
+            return Err(anyhow!("--smooth must be at least 1"));
+        }
+
+        let output_id = args
+            .output_id
+            .or(file.output_id)
+            .unwrap_or_else(|| output::DEFAULT_OUTPUT_ID.to_string());
+        if output_id.trim().is_empty() {
+            return Err(anyhow!("--output-id can't be empty"));
+        }
+
+        let json_logs = args.json_logs || file.json_logs.unwrap_or(false);
+        let human = args.human || file.human.unwrap_or(false);
+        if human && json_logs {
+            return Err(anyhow!("--human and --json-logs can't be used together"));
+        }
+
+        let raw_mallinfo2 = args.raw_mallinfo2 || file.raw_mallinfo2.unwrap_or(false);
+        if raw_mallinfo2 {
+            memory_monitor::check_mallinfo2()?;
+        }
+
+        let color = match args.color.or(file.color) {
+            Some(mode) => ColorMode::parse(&mode)?,
+            None => ColorMode::Auto,
+        };
+
+        let gpu_init_retries = match args.gpu_init_retries {
+            Some(value) => value
+                .parse::<u32>()
+                .map_err(|_| anyhow!("Invalid --gpu-init-retries value: {}", value))?,
+            None => file.gpu_init_retries.unwrap_or(0),
+        };
+        let backend = args.backend.or(file.backend).as_deref().map(Backend::parse).transpose()?;
+
+        let renderer_churn = match args.renderer_churn.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.renderer_churn,
+        };
+        if renderer_churn.is_some_and(|interval| interval.is_zero()) {
+            return Err(anyhow!("--renderer-churn interval must be greater than 0"));
+        }
+
+        let dump_maps_interval = match args.dump_maps_interval.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.dump_maps_interval,
+        };
+        if dump_maps_interval.is_some_and(|interval| interval.is_zero()) {
+            return Err(anyhow!("--dump-maps-interval must be greater than 0"));
+        }
+
+        let timeline = match args.timeline.or(file.timeline) {
+            Some(path) => timeline::load(&path)?,
+            None => Vec::new(),
+        };
+
+        let start_delay = match args.start_delay.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.start_delay,
+        };
+
+        let pin_cpu = args
+            .pin_cpu
+            .or(file.pin_cpu)
+            .as_deref()
+            .map(affinity::parse_core_list)
+            .transpose()?;
+
+        let mut scene = SceneOptions::default();
+        if let Some(mode) = args.rescale.or(file.rescale) {
+            scene.rescale_mode = input::parse_rescale_mode(&mode)?;
+        }
+        if let Some(align) = args.align.or(file.align) {
+            (scene.horizontal_align, scene.vertical_align) = input::parse_align(&align)?;
+        }
+        if let Some(color) = args.bg.or(file.bg) {
+            scene.background_color = Some(input::parse_rgba_color(&color)?);
+        }
+        if let Some(border) = args.border.or(file.border) {
+            scene.border = Some(input::parse_border(&border)?);
+        }
+        scene.box_shadow = args.shadow || file.shadow.unwrap_or(false);
+
+        // The animated view uses the --bg color when given
+        let animate_opacity = (args.animate_opacity || file.animate_opacity.unwrap_or(false))
+            .then(|| scene.background_color.unwrap_or(ANIMATED_VIEW_COLOR));
+        let transition = match args.transition.as_deref() {
+            Some(value) => Some(parse_duration(value)?),
+            None => file.transition,
+        };
+        if animate_opacity.is_some()
+            && (!timeline.is_empty() || renderer_churn.is_some() || transition.is_some())
+        {
+            return Err(anyhow!(
+                "--animate-opacity can't be used with --timeline, --renderer-churn or --transition"
+            ));
+        }
+        // The transition scene shows the startup web renderer, which the timeline
+        // unregisters on its first switch
+        if transition.is_some() && !timeline.is_empty() {
+            return Err(anyhow!("--transition can't be used with --timeline"));
+        }
+
+        let monitor_warmup = match args.monitor_warmup.as_deref() {
+            Some(value) => parse_duration(value)?,
+            None => file
+                .monitor_warmup
+                .unwrap_or(memory_monitor::DEFAULT_MONITOR_WARMUP),
+        };
+
+        let snapshot_history = match args.snapshot_history {
+            Some(value) => value
+                .parse::<usize>()
+                .map_err(|_| anyhow!("Invalid --snapshot-history value: {}", value))?,
+            None => file
+                .snapshot_history
+                .unwrap_or(memory_monitor::DEFAULT_SNAPSHOT_HISTORY),
+        };
+
+        let checked_fields = match (
+            args.check_fields.or(file.check_fields),
+            args.ignore_fields.or(file.ignore_fields),
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(anyhow!("--check-fields and --ignore-fields can't be used together"))
+            }
+            (Some(fields), None) => memory_monitor::parse_field_list(&fields)?,
+            (None, Some(fields)) => {
+                let ignored = memory_monitor::parse_field_list(&fields)?;
+                memory_monitor::DEFAULT_CHECKED_FIELDS
+                    .into_iter()
+                    .filter(|field| !ignored.contains(field))
+                    .collect()
+            }
+            (None, None) => memory_monitor::DEFAULT_CHECKED_FIELDS.to_vec(),
+        };
+
+        Ok(Config {
+            resolution: Resolution {
+                width: WIDTH,
+                height: HEIGHT,
+            },
+            framerate: Framerate {
+                num: FRAMERATE,
+                den: 1,
+            },
+            web_url,
+            web_gpu: args.web_gpu || file.web_gpu.unwrap_or(false),
+            scene,
+            allocate_ram,
+            release_ram_on_sigusr1: args.release_ram_on_sigusr1
+                || file.release_ram_on_sigusr1.unwrap_or(false),
+            monitor: MonitorOptions {
+                warmup: monitor_warmup,
+                baseline_path: args.baseline.or(file.baseline),
+                detailed_mem: args.detailed_mem || file.detailed_mem.unwrap_or(false),
+                web_mem: args.web_mem || file.web_mem.unwrap_or(false),
+                resource_counts: args.resource_counts || file.resource_counts.unwrap_or(false),
+                raw_mallinfo2,
+                pin_cpu: pin_cpu.clone(),
+                fail_on_overflow: args.fail_on_overflow || file.fail_on_overflow.unwrap_or(false),
+                checked_fields,
+                snapshot_history,
+                structured_logs: !human,
+                statsd_addr: args.statsd.or(file.statsd),
+                smooth,
+            },
+            consumer: ConsumerOptions {
+                shm_name: shm,
+                consume_rate,
+                snapshot_path: args.snapshot.or(file.snapshot),
+                pin_cpu,
+                detect_stall_frames: args.detect_stall_frames
+                    || file.detect_stall_frames.unwrap_or(false),
+                consumers,
+                tcp_addr: args.tcp_out.or(file.tcp_out),
+                hash_frames,
+                show_pts,
+                max_frames,
+                output_id,
+            },
+            bench,
+            max_runtime,
+            start_delay,
+            events_path: args.events.or(file.events),
+            quiet: args.quiet || file.quiet.unwrap_or(false),
+            color,
+            json_logs,
+            thread_stack,
+            animate_opacity,
+            transition,
+            dump_maps_interval,
+            dump_maps_dir: args
+                .dump_maps_dir
+                .or(file.dump_maps_dir)
+                .unwrap_or_else(|| PathBuf::from(DUMP_MAPS_DIR)),
+            dump_smaps: args.dump_smaps || file.dump_smaps.unwrap_or(false),
+            require_override: args.require_override || file.require_override.unwrap_or(false),
+            gpu_init_retries,
+            backend,
+            renderer_churn,
+            timeline,
+            profile_path: args.profile.or(file.profile),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_units_in_order() {
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("1m1ms").unwrap(), Duration::from_millis(60_001));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn parse_duration_treats_bare_numbers_as_seconds() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("0").unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn parse_duration_rejects_repeated_units() {
+        assert!(parse_duration("5s5s").is_err());
+    }
+
+    #[test]
+    fn parse_duration_rejects_units_out_of_order() {
+        assert!(parse_duration("30m1h").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Result};
+use std::ffi::CStr;
+use std::fs;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::procfs;
+
+/// Logs the host details, the GPU adapter and the effective configuration, so that a
+/// pasted log is enough to know where a run happened
+pub fn log_environment(config: &Config, adapter: &wgpu::AdapterInfo) {
+    let cpus = std::thread::available_parallelism()
+        .map(|cpus| cpus.to_string())
+        .unwrap_or_else(|_| "n/a".to_string());
+    let total_ram = match procfs::read_mem_total() {
+        Some(bytes) => format!("{:.2} GB", bytes as f64 / 1_073_741_824.0),
+        None => "n/a".to_string(),
+    };
+    let override_loaded = mallinfo_override_loaded();
+
+    info!(
+        "Environment:\n  OS: {}\n  kernel: {}\n  glibc: {}\n  CPUs: {}\n  total RAM: {}\n  GPU adapter: {} ({:?}, {:?}, driver {})\n  mallinfo override loaded: {}\n  config: {:?}",
+        os_name(),
+        kernel_version().unwrap_or_else(|| "n/a".to_string()),
+        glibc_version(),
+        cpus,
+        total_ram,
+        adapter.name,
+        adapter.backend,
+        adapter.device_type,
+        adapter.driver,
+        override_loaded,
+        config,
+    );
+}
+
+/// Warns when libmallinfo_override isn't preloaded, since mallinfo() then wraps
+/// around and CEF ends up crashing. With `required` (--require-override) this is an
+/// error instead, so automated runs can't silently test the unpatched allocator.
+pub fn check_mallinfo_override(required: bool) -> Result<()> {
+    if mallinfo_override_loaded() {
+        return Ok(());
+    }
+    if required {
+        return Err(anyhow!(
+            "libmallinfo_override is not loaded (--require-override). Run with LD_PRELOAD=target/debug/libmallinfo_override.so"
+        ));
+    }
+    warn!("libmallinfo_override is not loaded, mallinfo() values are not clamped");
+    Ok(())
+}
+
+/// Whether the mallinfo() this process actually calls is the one from
+/// libmallinfo_override. Having the library mapped isn't enough: it only takes
+/// effect when it comes before libc in the lookup order, as with LD_PRELOAD.
+fn mallinfo_override_loaded() -> bool {
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"mallinfo".as_ptr()) };
+    if symbol.is_null() {
+        return false;
+    }
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(symbol, &mut info) } == 0 || info.dli_fname.is_null() {
+        return false;
+    }
+    let object = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    object
+        .rsplit('/')
+        .next()
+        .is_some_and(|file_name| file_name.starts_with("libmallinfo_override"))
+}
+
+/// Distribution name from /etc/os-release, falling back to the target OS
+fn os_name() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                line.strip_prefix("PRETTY_NAME=")
+                    .map(|name| name.trim_matches('"').to_string())
+            })
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_string())
+}
+
+/// Kernel release and machine from uname(2), e.g. "6.8.0-45-generic x86_64"
+fn kernel_version() -> Option<String> {
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return None;
+    }
+    let field = |chars: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(chars.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Some(format!("{} {}", field(&uts.release), field(&uts.machine)))
+}
+
+fn glibc_version() -> String {
+    unsafe { CStr::from_ptr(libc::gnu_get_libc_version()) }
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Failure categories of the setup steps, so callers can tell them apart without
+/// matching on messages
+#[derive(Debug, Error)]
+pub enum SmelterCrashError {
+    #[error("Invalid arguments")]
+    ArgParse(#[source] anyhow::Error),
+    #[error("Failed to initialize WGPU")]
+    GraphicsInit(#[source] anyhow::Error),
+    #[error("Failed to create compositor pipeline")]
+    PipelineInit(#[source] anyhow::Error),
+    #[error("Failed to set up web input")]
+    InputSetup(#[source] anyhow::Error),
+    #[error("Failed to set up raw output")]
+    OutputSetup(#[source] anyhow::Error),
+    #[error("Failed to run event loop")]
+    EventLoop(#[source] anyhow::Error),
+    #[error("Failed to open {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
@@ -0,0 +1,142 @@
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::error::SmelterCrashError;
+use crate::memory_monitor::{self, MemoryUsage};
+
+static EVENT_LOG: OnceLock<EventLog> = OnceLock::new();
+/// Previous event and the memory usage when it happened
+static LAST_EVENT: Mutex<Option<(&'static str, MemoryUsage)>> = Mutex::new(None);
+
+/// Pipeline lifecycle transitions
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PipelineStarted,
+    InputRegistered { renderer_id: String, url: String },
+    OutputRegistered { output_id: String },
+    /// The output scene was replaced, `source` names the feature that replaced it
+    SceneUpdated { output_id: String, source: &'static str },
+    OutputUnregistered { output_id: String },
+    FirstFrame,
+    ShutdownReason { reason: String },
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::PipelineStarted => "pipeline_started",
+            Event::InputRegistered { .. } => "input_registered",
+            Event::OutputRegistered { .. } => "output_registered",
+            Event::SceneUpdated { .. } => "scene_updated",
+            Event::OutputUnregistered { .. } => "output_unregistered",
+            Event::FirstFrame => "first_frame",
+            Event::ShutdownReason { .. } => "shutdown_reason",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp_ms: u128,
+    elapsed_ms: u128,
+    #[serde(flatten)]
+    event: &'a Event,
+    memory: MemoryUsage,
+}
+
+/// Append-only NDJSON log of lifecycle events
+pub struct EventLog {
+    file: Mutex<File>,
+    start_time: Instant,
+}
+
+impl EventLog {
+    fn open(path: &Path) -> Result<Self, SmelterCrashError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|source| SmelterCrashError::Io {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        Ok(Self {
+            file: Mutex::new(file),
+            start_time: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, event: &Event, memory: MemoryUsage) {
+        let record = Record {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            elapsed_ms: self.start_time.elapsed().as_millis(),
+            event,
+            memory,
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                let mut file = self.file.lock().unwrap();
+                writeln!(file, "{}", line)?;
+                file.flush()?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            warn!("Failed to write event log: {:#}", e);
+        }
+    }
+}
+
+/// Enables the global event log
+pub fn init(path: &Path) -> Result<(), SmelterCrashError> {
+    let log = EventLog::open(path)?;
+    let _ = EVENT_LOG.set(log);
+    Ok(())
+}
+
+/// Logs how much memory was allocated since the previous event, and records the event
+/// to the global event log if enabled with --events
+pub fn record(event: Event) {
+    let memory = memory_monitor::current_usage();
+
+    let previous = LAST_EVENT.lock().unwrap().replace((event.name(), memory));
+    if let Some((previous_name, previous_memory)) = previous {
+        // Unsigned like the monitor's proximity, uordblks wraps past INT_MAX
+        let uordblks_delta =
+            memory.uordblks as u32 as i64 - previous_memory.uordblks as u32 as i64;
+        let rss_delta = match (memory.rss, previous_memory.rss) {
+            (Some(rss), Some(previous_rss)) => Some(rss as i64 - previous_rss as i64),
+            _ => None,
+        };
+        if memory_monitor::structured_logs() {
+            info!(
+                from = previous_name,
+                to = event.name(),
+                uordblks_delta,
+                rss_delta,
+                "Memory cost"
+            );
+        } else {
+            info!(
+                "Memory cost from {} to {}: uordblks {}, RSS {}",
+                previous_name,
+                event.name(),
+                memory_monitor::signed_human_bytes(uordblks_delta),
+                rss_delta.map_or("n/a".to_string(), memory_monitor::signed_human_bytes)
+            );
+        }
+    }
+
+    if let Some(log) = EVENT_LOG.get() {
+        log.record(&event, memory);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Context, Result};
+use compositor_render::{Frame, FrameData};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Default destination of the --hash-frames log
+pub const DEFAULT_HASH_PATH: &str = "frame_hashes.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Crc32,
+    XxHash,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Parses "crc32", "xxhash" or "blake3"
+    pub fn parse(input: &str) -> Result<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "crc32" => Ok(HashAlgorithm::Crc32),
+            "xxhash" => Ok(HashAlgorithm::XxHash),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            other => Err(anyhow!(
+                "Invalid hash algorithm: '{}'. Use crc32, xxhash or blake3",
+                other
+            )),
+        }
+    }
+
+    fn hash(self, chunks: &[&[u8]]) -> String {
+        match self {
+            HashAlgorithm::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new();
+                chunks.iter().for_each(|chunk| hasher.update(chunk));
+                format!("{:08x}", hasher.finalize())
+            }
+            HashAlgorithm::XxHash => {
+                let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+                chunks.iter().for_each(|chunk| hasher.update(chunk));
+                format!("{:016x}", hasher.digest())
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                chunks.iter().for_each(|chunk| {
+                    hasher.update(chunk);
+                });
+                hasher.finalize().to_hex().to_string()
+            }
+        }
+    }
+}
+
+/// Writes `<pts_us> <hash>` for every output frame, to diff runs for bit-exactness
+///
+/// Frames are keyed by PTS rather than arrival order, which varies between runs with
+/// several consumer threads: sort the logs before diffing them. Hashing reads every
+/// byte of the frame, so this costs noticeably more CPU than just dropping frames.
+/// Lines are flushed as they are written so the log is complete up to a crash.
+pub struct FrameHashLog {
+    algorithm: HashAlgorithm,
+    path: PathBuf,
+    file: LineWriter<File>,
+    logged_unsupported: bool,
+}
+
+impl FrameHashLog {
+    pub fn create(algorithm: HashAlgorithm, path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create frame hash log {}", path.display()))?;
+        info!("Logging {:?} frame hashes to {}", algorithm, path.display());
+        Ok(Self {
+            algorithm,
+            path: path.to_path_buf(),
+            file: LineWriter::new(file),
+            logged_unsupported: false,
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) {
+        let chunks: Vec<&[u8]> = match &frame.data {
+            FrameData::PlanarYuv420(planes) | FrameData::PlanarYuvJ420(planes) => {
+                vec![&planes.y_plane, &planes.u_plane, &planes.v_plane]
+            }
+            FrameData::InterleavedYuv422(data) => vec![data],
+            _ => {
+                if !self.logged_unsupported {
+                    self.logged_unsupported = true;
+                    warn!("Unsupported frame format for frame hashing, frames are not hashed");
+                }
+                return;
+            }
+        };
+
+        let hash = self.algorithm.hash(&chunks);
+        if let Err(e) = writeln!(self.file, "{} {}", frame.pts.as_micros(), hash) {
+            warn!("Failed to write frame hash to {}: {}", self.path.display(), e);
+        }
+    }
+}
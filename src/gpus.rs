@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Result};
+use tracing::info;
+
+/// wgpu backend forced with --backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Vulkan,
+    Gl,
+    Metal,
+    Dx12,
+}
+
+impl Backend {
+    /// Parses a --backend value, rejecting backends that don't exist on this platform
+    pub fn parse(input: &str) -> Result<Self> {
+        let (backend, available) = match input.trim().to_lowercase().as_str() {
+            "vulkan" => (Backend::Vulkan, cfg!(any(target_os = "linux", target_os = "windows"))),
+            "gl" => (Backend::Gl, true),
+            "metal" => (Backend::Metal, cfg!(target_os = "macos")),
+            "dx12" => (Backend::Dx12, cfg!(target_os = "windows")),
+            other => {
+                return Err(anyhow!(
+                    "Unknown backend: '{}'. Use vulkan, gl, metal or dx12",
+                    other
+                ))
+            }
+        };
+        if !available {
+            return Err(anyhow!(
+                "The {} backend is not available on this platform",
+                backend.name()
+            ));
+        }
+        Ok(backend)
+    }
+
+    /// Name as understood by WGPU_BACKEND
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Vulkan => "vulkan",
+            Backend::Gl => "gl",
+            Backend::Metal => "metal",
+            Backend::Dx12 => "dx12",
+        }
+    }
+
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+        }
+    }
+}
+
+/// Restricts wgpu to `backend` through WGPU_BACKEND, after checking it has an adapter
+///
+/// GraphicsContextOptions has no backend field, so the environment variable is the
+/// only way in. Must run before other threads are started, as it sets the variable.
+pub fn force_backend(backend: Backend) -> Result<()> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: backend.backends(),
+        ..Default::default()
+    });
+    if instance.enumerate_adapters(backend.backends()).is_empty() {
+        return Err(anyhow!("No GPU adapter available for the {} backend (--backend)", backend.name()));
+    }
+    std::env::set_var("WGPU_BACKEND", backend.name());
+    info!("Forcing the {} backend (--backend)", backend.name());
+    Ok(())
+}
+
+/// Fails if the graphics context ended up on another backend than the forced one
+pub fn check_adapter_backend(backend: Backend, adapter: &wgpu::AdapterInfo) -> Result<()> {
+    if backend.backends().contains(wgpu::Backends::from(adapter.backend)) {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "The graphics context uses {:?} instead of the {} backend requested with --backend",
+        adapter.backend,
+        backend.name()
+    ))
+}
+
+/// Prints the adapters wgpu can see on this machine, for `smelter-crash list-gpus`
+///
+/// Honors the WGPU_BACKEND environment variable.
+pub fn list_gpus() {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+    let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+    if adapters.is_empty() {
+        println!("No GPU adapters found");
+        return;
+    }
+
+    println!("GPU adapters");
+    for (index, adapter) in adapters.iter().enumerate() {
+        let info = adapter.get_info();
+        println!(
+            "  {:<3} {} ({:?}, {:?}, driver {})",
+            index, info.name, info.backend, info.device_type, info.driver
+        );
+    }
+}
@@ -1,57 +1,489 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use compositor_pipeline::Pipeline;
 use compositor_render::scene::*;
 use compositor_render::web_renderer::{WebEmbeddingMethod, WebRendererSpec};
-use compositor_render::{RendererId, RendererSpec, Resolution};
+use compositor_render::{Framerate, OutputId, RendererId, RendererSpec, Resolution};
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use tracing::info;
-
-const WIDTH: usize = 1920;
-const HEIGHT: usize = 1080;
-const WEB_URL: &str = "https://google.com";
-
-pub fn setup_web_input(pipeline: &Arc<Mutex<Pipeline>>) -> Result<Component> {
-    let web_renderer_id = RendererId(Arc::from("web_renderer"));
-    Pipeline::register_renderer(
-        pipeline,
-        web_renderer_id.clone(),
-        RendererSpec::WebRenderer(WebRendererSpec {
-            url: WEB_URL.to_string(),
-            resolution: Resolution {
-                width: WIDTH,
-                height: HEIGHT,
-            },
-            embedding_method: WebEmbeddingMethod::NativeEmbeddingOverContent,
-        }),
-    )?;
-    info!("Registered web renderer: {}", WEB_URL);
-
-    // Create scene with web renderer wrapped in a Rescaler
-    Ok(Component::Rescaler(RescalerComponent {
-        id: None,
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::error::SmelterCrashError;
+use crate::events::{self, Event};
+use crate::threads;
+
+/// How the input is laid out in the output frame
+#[derive(Debug, Clone)]
+pub struct SceneOptions {
+    pub rescale_mode: RescaleMode,
+    pub horizontal_align: HorizontalAlign,
+    pub vertical_align: VerticalAlign,
+    /// Color of a view placed behind the rescaled content, visible in the letterbox areas
+    pub background_color: Option<RGBAColor>,
+    /// Width and color of a border drawn around the rescaled content
+    pub border: Option<(f32, RGBAColor)>,
+    /// Draw a drop shadow under the rescaled content
+    pub box_shadow: bool,
+}
+
+impl Default for SceneOptions {
+    fn default() -> Self {
+        Self {
+            rescale_mode: RescaleMode::Fit,
+            horizontal_align: HorizontalAlign::Center,
+            vertical_align: VerticalAlign::Center,
+            background_color: None,
+            border: None,
+            box_shadow: false,
+        }
+    }
+}
+
+pub fn parse_rescale_mode(input: &str) -> Result<RescaleMode> {
+    match input.trim().to_lowercase().as_str() {
+        "fit" => Ok(RescaleMode::Fit),
+        "fill" => Ok(RescaleMode::Fill),
+        "stretch" => Err(anyhow!(
+            "Rescale mode 'stretch' is not supported by the compositor. Use fit or fill"
+        )),
+        other => Err(anyhow!("Invalid rescale mode: '{}'. Use fit or fill", other)),
+    }
+}
+
+/// Parses "<horizontal>:<vertical>" (e.g. "left:top"), or a single value used for both axes
+pub fn parse_align(input: &str) -> Result<(HorizontalAlign, VerticalAlign)> {
+    let input = input.trim().to_lowercase();
+    let (horizontal, vertical) = input.split_once(':').unwrap_or((&input, &input));
+
+    let horizontal = match horizontal {
+        "left" => HorizontalAlign::Left,
+        "right" => HorizontalAlign::Right,
+        "center" => HorizontalAlign::Center,
+        "justified" => HorizontalAlign::Justified,
+        other => {
+            return Err(anyhow!(
+                "Invalid horizontal align: '{}'. Use left, right, center or justified",
+                other
+            ))
+        }
+    };
+    let vertical = match vertical {
+        "top" => VerticalAlign::Top,
+        "bottom" => VerticalAlign::Bottom,
+        "center" => VerticalAlign::Center,
+        "justified" => VerticalAlign::Justified,
+        other => {
+            return Err(anyhow!(
+                "Invalid vertical align: '{}'. Use top, bottom, center or justified",
+                other
+            ))
+        }
+    };
+    Ok((horizontal, vertical))
+}
+
+/// Parses "<width>:<color>" (e.g. "8:FF0000") into a border width and color
+pub fn parse_border(input: &str) -> Result<(f32, RGBAColor)> {
+    let (width, color) = input
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid border: '{}'. Use <width>:<RRGGBB>", input))?;
+    let width = width
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|width| width.is_finite() && *width >= 0.0)
+        .ok_or_else(|| anyhow!("Invalid border width: '{}'", width))?;
+    Ok((width, parse_rgba_color(color)?))
+}
+
+/// Parses a "RRGGBB" or "RRGGBBAA" hex color, with an optional leading '#'
+pub fn parse_rgba_color(input: &str) -> Result<RGBAColor> {
+    let hex = input.trim().trim_start_matches('#');
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid color: '{}'. Use RRGGBB or RRGGBBAA", input));
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16);
+    let alpha = if hex.len() == 8 { channel(3)? } else { 255 };
+    Ok(RGBAColor(channel(0)?, channel(1)?, channel(2)?, alpha))
+}
+
+/// Duration of a full opacity cycle of --animate-opacity
+const OPACITY_PERIOD_SECS: f64 = 2.0;
+
+/// Id of the rescaler wrapping the web view
+const RESCALER_ID: &str = "web_rescaler";
+/// Delay between the output registration and the --transition scene update
+const TRANSITION_DELAY: Duration = Duration::from_secs(1);
+
+/// Id of the web renderer registered at startup
+pub const WEB_RENDERER_ID: &str = "web_renderer";
+
+pub fn setup_web_input(
+    pipeline: &Arc<Mutex<Pipeline>>,
+    config: &Config,
+) -> Result<Component, SmelterCrashError> {
+    register_web_input(pipeline, config).map_err(SmelterCrashError::InputSetup)
+}
+
+/// Converts a local HTML file path into an absolute `file://` URL
+pub fn html_file_url(path: &Path) -> Result<String> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve HTML file {}", path.display()))?;
+    Ok(format!("file://{}", percent_encode_path(&path)))
+}
+
+/// Percent-encodes every byte of the path except unreserved characters and '/'
+fn percent_encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decodes the path of a `file://` URL, `None` if an escape is malformed
+fn file_url_path(url_path: &str) -> Option<PathBuf> {
+    let bytes = url_path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(decoded)))
+}
+
+fn register_web_input(pipeline: &Arc<Mutex<Pipeline>>, config: &Config) -> Result<Component> {
+    let web_renderer_id = RendererId(Arc::from(WEB_RENDERER_ID));
+    register_web_renderer(pipeline, &web_renderer_id, &config.web_url, config.resolution)?;
+    Ok(build_scene(&web_renderer_id, &config.scene))
+}
+
+/// Registers a web renderer showing `url`
+pub fn register_web_renderer(
+    pipeline: &Arc<Mutex<Pipeline>>,
+    renderer_id: &RendererId,
+    url: &str,
+    resolution: Resolution,
+) -> Result<()> {
+    // Fail early rather than letting the renderer show an error page
+    if let Some(url_path) = url.strip_prefix("file://") {
+        let path = file_url_path(url_path)
+            .ok_or_else(|| anyhow!("Invalid percent-encoding in file URL: {}", url))?;
+        if !path.is_file() {
+            return Err(anyhow!("Web renderer file does not exist: {}", path.display()));
+        }
+    }
+
+    Pipeline::register_renderer(pipeline, renderer_id.clone(), web_renderer_spec(url, resolution))?;
+    info!("Registered web renderer: {}", url);
+    events::record(Event::InputRegistered {
+        renderer_id: renderer_id.0.to_string(),
+        url: url.to_string(),
+    });
+    Ok(())
+}
+
+pub fn web_renderer_spec(url: &str, resolution: Resolution) -> RendererSpec {
+    RendererSpec::WebRenderer(WebRendererSpec {
+        url: url.to_string(),
+        resolution,
+        embedding_method: WebEmbeddingMethod::NativeEmbeddingOverContent,
+    })
+}
+
+/// Scene showing the web renderer, laid out according to the scene options
+pub fn build_scene(renderer_id: &RendererId, scene: &SceneOptions) -> Component {
+    let position = Position::Static {
+        width: None,
+        height: None,
+    };
+    build_scene_with(renderer_id, scene, position, None)
+}
+
+/// Same as [`build_scene`] with the content shrunk to half the output size through a
+/// transition of the given duration
+pub fn build_transition_scene(
+    renderer_id: &RendererId,
+    scene: &SceneOptions,
+    resolution: Resolution,
+    duration: Duration,
+) -> Component {
+    let position = Position::Static {
+        width: Some(resolution.width as f32 / 2.0),
+        height: Some(resolution.height as f32 / 2.0),
+    };
+    let transition = Transition {
+        duration,
+        interpolation_kind: InterpolationKind::Linear,
+    };
+    build_scene_with(renderer_id, scene, position, Some(transition))
+}
+
+/// Applies the transition scene shortly after the output was registered, to go
+/// through the transition animation code
+pub fn start_transition(
+    pipeline: Arc<Mutex<Pipeline>>,
+    output_id: OutputId,
+    config: &Config,
+    duration: Duration,
+) {
+    let scene = build_transition_scene(
+        &RendererId(Arc::from(WEB_RENDERER_ID)),
+        &config.scene,
+        config.resolution,
+        duration,
+    );
+    threads::spawn("transition", move || {
+        std::thread::sleep(TRANSITION_DELAY);
+        let result = pipeline.lock().unwrap().update_output(output_id.clone(), Some(scene), None);
+        match result {
+            Ok(()) => {
+                info!("Started a {:?} transition (--transition)", duration);
+                events::record(Event::SceneUpdated {
+                    output_id: output_id.0.to_string(),
+                    source: "transition",
+                });
+            }
+            Err(e) => warn!("Failed to apply the transition scene: {}", e),
+        }
+    });
+}
+
+fn build_scene_with(
+    renderer_id: &RendererId,
+    scene: &SceneOptions,
+    position: Position,
+    transition: Option<Transition>,
+) -> Component {
+    let (border_width, border_color) = scene.border.unwrap_or((0.0, RGBAColor(0, 0, 0, 0)));
+    let box_shadow = if scene.box_shadow {
+        vec![BoxShadow {
+            offset_x: 16.0,
+            offset_y: 16.0,
+            blur_radius: 32.0,
+            color: RGBAColor(0, 0, 0, 160),
+        }]
+    } else {
+        vec![]
+    };
+
+    // Web renderer wrapped in a Rescaler
+    let rescaler = Component::Rescaler(RescalerComponent {
+        // Transitions only apply between components with the same id
+        id: Some(ComponentId(Arc::from(RESCALER_ID))),
         child: Box::new(Component::WebView(WebViewComponent {
             id: None,
             children: vec![],
-            instance_id: web_renderer_id.clone(),
+            instance_id: renderer_id.clone(),
         })),
+        position,
+        transition,
+        mode: scene.rescale_mode,
+        horizontal_align: scene.horizontal_align,
+        vertical_align: scene.vertical_align,
+        border_radius: BorderRadius::ZERO,
+        border_width,
+        border_color,
+        box_shadow,
+    });
+
+    match scene.background_color {
+        Some(color) => with_background(rescaler, color),
+        None => rescaler,
+    }
+}
+
+/// Wraps the component in a full-size view filled with the given color
+fn with_background(child: Component, color: RGBAColor) -> Component {
+    filled_view(vec![child], color)
+}
+
+/// Full-size view of the given color, its alpha scaled by `opacity` (0.0 to 1.0)
+pub fn opacity_scene(color: RGBAColor, opacity: f32) -> Component {
+    let alpha = (color.3 as f32 * opacity.clamp(0.0, 1.0)).round() as u8;
+    filled_view(vec![], RGBAColor(color.0, color.1, color.2, alpha))
+}
+
+/// Updates the output scene once per frame with an opacity going from 1 to 0 and
+/// back, to drive the scene update path without any web or video input
+pub fn start_opacity_animation(
+    pipeline: Arc<Mutex<Pipeline>>,
+    output_id: OutputId,
+    color: RGBAColor,
+    framerate: Framerate,
+) {
+    let frame_interval = Duration::from_secs_f64(framerate.den as f64 / framerate.num as f64);
+    info!("Animating the scene opacity every {:?} (--animate-opacity)", frame_interval);
+
+    threads::spawn("opacity_anim", move || {
+        let start = Instant::now();
+        let mut recorded = false;
+        loop {
+            std::thread::sleep(frame_interval);
+            let phase = start.elapsed().as_secs_f64() % OPACITY_PERIOD_SECS / OPACITY_PERIOD_SECS;
+            let opacity = (1.0 - 2.0 * phase).abs() as f32;
+            let result = pipeline.lock().unwrap().update_output(
+                output_id.clone(),
+                Some(opacity_scene(color, opacity)),
+                None,
+            );
+            if let Err(e) = result {
+                warn!("Failed to update the animated scene, stopping the animation: {}", e);
+                break;
+            }
+            // Only the first update: one event per frame would drown the event log
+            if !recorded {
+                recorded = true;
+                events::record(Event::SceneUpdated {
+                    output_id: output_id.0.to_string(),
+                    source: "animate_opacity",
+                });
+            }
+        }
+    });
+}
+
+fn filled_view(children: Vec<Component>, color: RGBAColor) -> Component {
+    Component::View(ViewComponent {
+        id: None,
+        children,
+        direction: ViewChildrenDirection::Row,
         position: Position::Static {
             width: None,
             height: None,
         },
         transition: None,
-        mode: RescaleMode::Fit,
-        horizontal_align: HorizontalAlign::Center,
-        vertical_align: VerticalAlign::Center,
+        overflow: Overflow::Hidden,
+        background_color: color,
         border_radius: BorderRadius::ZERO,
         border_width: 0.0,
         border_color: RGBAColor(0, 0, 0, 0),
         box_shadow: vec![],
-    }))
+        padding: Padding::default(),
+    })
 }
 
-pub fn resolution() -> Resolution {
-    Resolution {
-        width: WIDTH,
-        height: HEIGHT,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(color: RGBAColor) -> (u8, u8, u8, u8) {
+        let RGBAColor(r, g, b, a) = color;
+        (r, g, b, a)
+    }
+
+    #[test]
+    fn parse_rgba_color_valid() {
+        assert_eq!(rgba(parse_rgba_color("FF8000").unwrap()), (255, 128, 0, 255));
+        assert_eq!(rgba(parse_rgba_color("#ff800080").unwrap()), (255, 128, 0, 128));
+        assert_eq!(rgba(parse_rgba_color(" 000000 ").unwrap()), (0, 0, 0, 255));
+    }
+
+    #[test]
+    fn parse_rgba_color_invalid() {
+        assert!(parse_rgba_color("").is_err());
+        assert!(parse_rgba_color("FFF").is_err());
+        assert!(parse_rgba_color("FF80001").is_err());
+        assert!(parse_rgba_color("FF8000FF0").is_err());
+        assert!(parse_rgba_color("GG8000").is_err());
+        assert!(parse_rgba_color("+F8000").is_err());
+        assert!(parse_rgba_color("ÿÿÿ").is_err());
+    }
+
+    #[test]
+    fn parse_align_valid() {
+        assert!(matches!(
+            parse_align("left:top").unwrap(),
+            (HorizontalAlign::Left, VerticalAlign::Top)
+        ));
+        assert!(matches!(
+            parse_align(" Right:Bottom ").unwrap(),
+            (HorizontalAlign::Right, VerticalAlign::Bottom)
+        ));
+        assert!(matches!(
+            parse_align("center").unwrap(),
+            (HorizontalAlign::Center, VerticalAlign::Center)
+        ));
+        assert!(matches!(
+            parse_align("justified").unwrap(),
+            (HorizontalAlign::Justified, VerticalAlign::Justified)
+        ));
+    }
+
+    #[test]
+    fn parse_align_invalid() {
+        assert!(parse_align("").is_err());
+        assert!(parse_align("left").is_err());
+        assert!(parse_align("top:left").is_err());
+        assert!(parse_align("left:top:center").is_err());
+    }
+
+    #[test]
+    fn parse_border_valid() {
+        let (width, color) = parse_border("8:FF0000").unwrap();
+        assert_eq!(width, 8.0);
+        assert_eq!(rgba(color), (255, 0, 0, 255));
+        let (width, color) = parse_border("0.5:#00FF0080").unwrap();
+        assert_eq!(width, 0.5);
+        assert_eq!(rgba(color), (0, 255, 0, 128));
+    }
+
+    #[test]
+    fn html_file_url_round_trip() {
+        let dir = std::env::temp_dir().join(format!("smelter-crash-url-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a b%20c 100% é.html");
+        std::fs::write(&file, "").unwrap();
+
+        let url = html_file_url(&file).unwrap();
+        let canonical = file.canonicalize().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(url.ends_with("/a%20b%2520c%20100%25%20%C3%A9.html"), "{}", url);
+        assert_eq!(file_url_path(url.strip_prefix("file://").unwrap()).unwrap(), canonical);
+    }
+
+    #[test]
+    fn file_url_path_round_trip_non_utf8() {
+        let path = PathBuf::from(OsString::from_vec(b"/tmp/\xff\x00x?#.html".to_vec()));
+        let encoded = percent_encode_path(&path);
+        assert!(encoded.is_ascii());
+        assert_eq!(file_url_path(&encoded).unwrap(), path);
+    }
+
+    #[test]
+    fn file_url_path_malformed() {
+        assert!(file_url_path("/a%").is_none());
+        assert!(file_url_path("/a%2").is_none());
+        assert!(file_url_path("/a%zz").is_none());
+        assert!(file_url_path("/a%+1").is_none());
+    }
+
+    #[test]
+    fn parse_border_invalid() {
+        assert!(parse_border("").is_err());
+        assert!(parse_border("8").is_err());
+        assert!(parse_border("-1:FF0000").is_err());
+        assert!(parse_border("inf:FF0000").is_err());
+        assert!(parse_border("8:FF00").is_err());
+        assert!(parse_border("8:XX0000").is_err());
     }
 }
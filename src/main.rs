@@ -1,60 +1,148 @@
+mod affinity;
+#[cfg(feature = "alloc-counters")]
+mod alloc_counter;
 mod args;
+mod bench;
+mod churn;
+mod config;
+mod environment;
+mod error;
+mod events;
+mod frame_hash;
+mod gpus;
 mod input;
+mod maps_dump;
 mod memory_monitor;
 mod output;
+mod procfs;
+mod profile;
 mod ram;
+mod shm;
+mod shutdown;
+mod signals;
+mod snapshot;
+mod stall;
+mod statsd;
+mod tcp;
+mod threads;
+mod timeline;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use compositor_pipeline::pipeline::GraphicsContext;
 use compositor_pipeline::Pipeline;
-use compositor_render::{EventLoop, Framerate, OutputId};
+use compositor_render::{EventLoop, OutputId, RendererId};
+use error::SmelterCrashError;
+use serde::Serialize;
+use shutdown::ExitReason;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
 
-fn main() -> Result<()> {
-    // Parse command line arguments
+/// Wait before the first --gpu-init-retries retry, doubled after each failure
+const GPU_INIT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const GPU_INIT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How often the memory monitor supervisor checks the last sample time
+const MONITOR_SUPERVISOR_TICK: Duration = Duration::from_secs(1);
+
+fn main() {
+    let reason = match run() {
+        Ok(()) => ExitReason::Completed,
+        Err(e) => {
+            // Same output as returning the error from main, logging may not be set up yet
+            eprintln!("Error: {:?}", e);
+            ExitReason::Error
+        }
+    };
+    shutdown::shutdown(reason);
+}
+
+fn run() -> Result<()> {
+    // Parse and validate command line arguments
     let args = args::Args::parse()?;
+    if args.list_gpus {
+        gpus::list_gpus();
+        return Ok(());
+    }
+    let config = config::Config::try_from(args)?;
 
     // Initialize logging early
-    tracing_subscriber::fmt()
+    // --quiet keeps only warnings and errors (periodic monitor reports are info)
+    let log_filter = if config.quiet {
+        "smelter_crash=warn,compositor_pipeline=warn,compositor_render=warn,compositor_chromium=warn"
+    } else {
+        "smelter_crash=info,compositor_pipeline=warn,compositor_render=warn,compositor_chromium=info"
+    };
+    let subscriber = tracing_subscriber::fmt()
         .with_max_level(tracing::Level::INFO)
-        .with_env_filter("smelter_crash=info,compositor_pipeline=warn,compositor_render=warn,compositor_chromium=info")
-        .init();
+        .with_env_filter(log_filter);
+    if config.json_logs {
+        subscriber.json().with_ansi(false).init();
+    } else {
+        subscriber.with_ansi(config.color.enabled()).init();
+    }
+    memory_monitor::set_structured_logs(config.monitor.structured_logs);
 
     info!("Starting minimal smelter compositor");
+    environment::check_mallinfo_override(config.require_override)?;
+    if let Some(backend) = config.backend {
+        gpus::force_backend(backend)?;
+    }
+
+    if let Some(bytes) = config.thread_stack {
+        threads::set_stack_size(bytes);
+    }
+
+    if let Some(path) = &config.events_path {
+        events::init(path)?;
+        info!("Recording lifecycle events to {}", path.display());
+    }
+
+    if let Some(path) = &config.profile_path {
+        profile::start(path)?;
+    }
+
+    if let Some(limit) = config.max_runtime {
+        start_max_runtime_watchdog(limit);
+    }
+
+    // SIGUSR1 requests an immediate memory snapshot, SIGUSR2 an output frame snapshot
+    signals::install()?;
+    shutdown::start_signal_watch();
 
     // Start memory monitor
-    memory_monitor::start_memory_monitor();
+    memory_monitor::start_memory_monitor(&config.monitor)?;
+    start_monitor_supervisor(config.monitor.clone());
+
+    if let Some(interval) = config.dump_maps_interval {
+        maps_dump::start_maps_dump(config.dump_maps_dir.clone(), interval, config.dump_smaps)?;
+    }
 
     // Allocate and hold RAM if requested
-    if let Some(ram_size) = args.allocate_ram {
-        ram::allocate_and_hold(ram_size)?;
+    if let Some(bytes) = config.allocate_ram {
+        ram::allocate_and_hold(bytes, config.release_ram_on_sigusr1)?;
     }
 
     // Initialize graphics context
-    let graphics_context = GraphicsContext::new(compositor_pipeline::pipeline::GraphicsContextOptions {
-        force_gpu: false,
-        features: wgpu::Features::PUSH_CONSTANTS | wgpu::Features::TEXTURE_BINDING_ARRAY,
-        limits: wgpu::Limits::default(),
-        compatible_surface: None,
-        libvulkan_path: None,
-    })
-    .context("Failed to initialize WGPU")?;
+    let graphics_context = init_graphics_context(config.gpu_init_retries)?;
+    let adapter = graphics_context.adapter.get_info();
+    environment::log_environment(&config, &adapter);
+    if let Some(backend) = config.backend {
+        gpus::check_adapter_backend(backend, &adapter)?;
+    }
 
     // Create pipeline
-    let (pipeline, event_loop) = Pipeline::new(compositor_pipeline::pipeline::Options {
+    let options = compositor_pipeline::pipeline::Options {
         queue_options: compositor_pipeline::queue::QueueOptions {
             default_buffer_duration: Duration::ZERO,
             ahead_of_time_processing: false,
-            output_framerate: Framerate { num: 30, den: 1 },
+            output_framerate: config.framerate,
             run_late_scheduled_events: true,
             never_drop_output_frames: true, // Never drop frames - use blocking send instead of send_deadline
         },
         stream_fallback_timeout: Duration::from_millis(500),
         web_renderer: compositor_render::web_renderer::WebRendererInitOptions {
             enable: true,
-            enable_gpu: false,
+            enable_gpu: config.web_gpu,
         },
         force_gpu: false,
         download_root: std::env::temp_dir(),
@@ -67,35 +155,215 @@ fn main() -> Result<()> {
         start_whip_whep: false,
         tokio_rt: None,
         rendering_mode: compositor_render::RenderingMode::GpuOptimized,
-    })
-    .context("Failed to create compositor pipeline")?;
+    };
+    EffectiveOptions::new(&options, &config).log();
+    let (pipeline, event_loop) =
+        Pipeline::new(options).map_err(|e| SmelterCrashError::PipelineInit(e.into()))?;
+
+    // CEF is initialized by now and Chromium may have replaced the crash handlers
+    signals::install_crash_handlers()?;
 
     let pipeline = Arc::new(Mutex::new(pipeline));
     Pipeline::start(&pipeline);
+    let pipeline_started = Instant::now();
     info!("Pipeline started");
+    events::record(events::Event::PipelineStarted);
+
+    if let Some(delay) = config.start_delay {
+        info!("Waiting {:?} before registering the input and output (--start-delay)", delay);
+        std::thread::sleep(delay);
+    }
 
-    // Setup web input
-    let scene = input::setup_web_input(&pipeline)?;
+    // Setup web input, or a plain animated view with --animate-opacity
+    let scene = match config.animate_opacity {
+        Some(color) => input::opacity_scene(color, 1.0),
+        None => input::setup_web_input(&pipeline, &config)?,
+    };
 
     // Setup raw output
-    let output_id = output::setup_raw_output(&pipeline, scene, input::resolution())?;
+    let mut raw_output = output::setup_raw_output(&pipeline, scene, &config, pipeline_started)?;
+
+    if let Some(duration) = config.transition {
+        input::start_transition(
+            pipeline.clone(),
+            raw_output.output_id.clone(),
+            &config,
+            duration,
+        );
+    }
+
+    if let Some(color) = config.animate_opacity {
+        input::start_opacity_animation(
+            pipeline.clone(),
+            raw_output.output_id.clone(),
+            color,
+            config.framerate,
+        );
+    }
+
+    if !config.timeline.is_empty() {
+        timeline::start(
+            pipeline.clone(),
+            raw_output.output_id.clone(),
+            RendererId(Arc::from(input::WEB_RENDERER_ID)),
+            &config,
+        );
+    }
+
+    if let Some(interval) = config.renderer_churn {
+        churn::start_renderer_churn(
+            pipeline.clone(),
+            config.web_url.clone(),
+            config.resolution,
+            interval,
+        );
+    }
+
+    if let Some(duration) = config.bench {
+        bench::start_bench(
+            duration,
+            raw_output.stats.clone(),
+            config.renderer_churn.is_some(),
+        );
+    }
+
+    if let Some(reached) = raw_output.max_frames_reached.take() {
+        bench::start_max_frames_watch(
+            reached,
+            pipeline.clone(),
+            raw_output.output_id.clone(),
+            raw_output.stats.clone(),
+            config.renderer_churn.is_some(),
+        );
+    }
 
     // Run with event loop (required for web rendering)
-    run_with_event_loop(event_loop, pipeline, output_id)?;
+    run_with_event_loop(event_loop, pipeline, raw_output.output_id)?;
 
     Ok(())
 }
 
+/// Creates the graphics context, retrying with an exponential backoff since the GPU
+/// driver can still be coming up on freshly booted machines
+fn init_graphics_context(retries: u32) -> Result<GraphicsContext, SmelterCrashError> {
+    let mut backoff = GPU_INIT_INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = GraphicsContext::new(compositor_pipeline::pipeline::GraphicsContextOptions {
+            force_gpu: false,
+            features: wgpu::Features::PUSH_CONSTANTS | wgpu::Features::TEXTURE_BINDING_ARRAY,
+            limits: wgpu::Limits::default(),
+            compatible_surface: None,
+            libvulkan_path: None,
+        });
+        match result {
+            Ok(graphics_context) => return Ok(graphics_context),
+            Err(e) if attempt <= retries => {
+                warn!(
+                    "Graphics context initialization failed (attempt {}/{}): {}. Retrying in {:?}",
+                    attempt,
+                    retries + 1,
+                    e,
+                    backoff
+                );
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(GPU_INIT_MAX_BACKOFF);
+            }
+            Err(e) => return Err(SmelterCrashError::GraphicsInit(e.into())),
+        }
+    }
+}
+
+/// Values passed to Pipeline::new, logged as JSON for bug reports since the
+/// compositor option types don't implement Serialize
+#[derive(Debug, Serialize)]
+struct EffectiveOptions {
+    width: usize,
+    height: usize,
+    framerate: String,
+    output: &'static str,
+    rendering_mode: String,
+    force_gpu: bool,
+    web_url: String,
+    web_renderer_enabled: bool,
+    web_renderer_gpu: bool,
+    default_buffer_duration_ms: u128,
+    stream_fallback_timeout_ms: u128,
+    ahead_of_time_processing: bool,
+    never_drop_output_frames: bool,
+    run_late_scheduled_events: bool,
+}
+
+impl EffectiveOptions {
+    fn new(options: &compositor_pipeline::pipeline::Options, config: &config::Config) -> Self {
+        let queue = &options.queue_options;
+        Self {
+            width: config.resolution.width,
+            height: config.resolution.height,
+            framerate: format!("{}/{}", queue.output_framerate.num, queue.output_framerate.den),
+            output: "raw",
+            rendering_mode: format!("{:?}", options.rendering_mode),
+            force_gpu: options.force_gpu,
+            web_url: config.web_url.clone(),
+            web_renderer_enabled: options.web_renderer.enable,
+            web_renderer_gpu: options.web_renderer.enable_gpu,
+            default_buffer_duration_ms: queue.default_buffer_duration.as_millis(),
+            stream_fallback_timeout_ms: options.stream_fallback_timeout.as_millis(),
+            ahead_of_time_processing: queue.ahead_of_time_processing,
+            never_drop_output_frames: queue.never_drop_output_frames,
+            run_late_scheduled_events: queue.run_late_scheduled_events,
+        }
+    }
+
+    fn log(&self) {
+        match serde_json::to_string(self) {
+            Ok(json) => info!("Effective pipeline options: {}", json),
+            Err(e) => warn!("Failed to serialize the effective pipeline options: {}", e),
+        }
+    }
+}
+
 fn run_with_event_loop(
     event_loop: Arc<dyn EventLoop>,
     _pipeline: Arc<Mutex<Pipeline>>,
     _output_id: OutputId,
-) -> Result<()> {
+) -> Result<(), SmelterCrashError> {
     // Raw output mode: run indefinitely
     info!("Running in raw output mode (press Ctrl+C to exit)");
 
-    // Run the CEF event loop on the main thread
-    event_loop.run().context("Failed to run event loop")?;
+    // Run the CEF event loop on the main thread. EventLoop only offers this blocking
+    // run(), with no way to pump it in slices, so the main thread can't do periodic
+    // work: every shutdown (signals, --bench, --max-frames, --max-runtime) is driven
+    // from its own thread through shutdown::shutdown, which exits the process.
+    event_loop
+        .run()
+        .map_err(|e| SmelterCrashError::EventLoop(e.into()))?;
 
     Ok(())
 }
+
+/// Restarts the memory monitor if it stops sampling, e.g. after a panic in its
+/// thread, so unattended runs don't silently lose the memory reports
+fn start_monitor_supervisor(options: memory_monitor::MonitorOptions) {
+    threads::spawn("monitor_watch", move || loop {
+        std::thread::sleep(MONITOR_SUPERVISOR_TICK);
+        if let Some(stale) = memory_monitor::staleness() {
+            error!("Memory monitor hasn't sampled for {:?}, restarting it", stale);
+            if let Err(e) = memory_monitor::restart_memory_monitor(&options) {
+                error!("Failed to restart the memory monitor: {:#}", e);
+                break;
+            }
+        }
+    });
+}
+
+/// Force-exits the process once the runtime cap is reached, whatever else is going on
+fn start_max_runtime_watchdog(limit: Duration) {
+    info!("Process will be stopped after {:?} (--max-runtime)", limit);
+    threads::spawn("max_runtime", move || {
+        std::thread::sleep(limit);
+        error!("Maximum runtime of {:?} reached", limit);
+        shutdown::shutdown(ExitReason::MaxRuntime);
+    });
+}
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::threads;
+
+/// Number of dumps kept in the directory, older ones are deleted
+const RETAINED_DUMPS: usize = 20;
+
+/// Periodically copies /proc/self/maps (and smaps if requested) to timestamped files
+/// in `dir`, keeping the latest ones
+pub fn start_maps_dump(dir: PathBuf, interval: Duration, include_smaps: bool) -> Result<()> {
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    info!(
+        "Dumping /proc/self/maps{} to {} every {:?}, keeping the last {}",
+        if include_smaps { " and smaps" } else { "" },
+        dir.display(),
+        interval,
+        RETAINED_DUMPS
+    );
+
+    threads::spawn("maps_dump", move || {
+        let sources: &[&str] = if include_smaps { &["maps", "smaps"] } else { &["maps"] };
+        let mut written: VecDeque<PathBuf> = VecDeque::new();
+        loop {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            for source in sources {
+                let path = dir.join(format!("{}-{}.txt", source, timestamp));
+                match dump(source, &path) {
+                    Ok(()) => written.push_back(path),
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
+
+            while written.len() > RETAINED_DUMPS * sources.len() {
+                if let Some(path) = written.pop_front() {
+                    let _ = fs::remove_file(path);
+                }
+            }
+            std::thread::sleep(interval);
+        }
+    });
+    Ok(())
+}
+
+/// procfs files report a size of 0, so they are read rather than copied
+fn dump(source: &str, path: &Path) -> Result<()> {
+    let content = fs::read(format!("/proc/self/{}", source))
+        .with_context(|| format!("Failed to read /proc/self/{}", source))?;
+    fs::write(path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -1,11 +1,63 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
 use std::thread;
 use std::time::{Duration, Instant};
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+
+use crate::affinity;
+use crate::procfs::{self, DescendantsRss, SmapsRollup};
+use crate::shutdown::{self, ExitReason};
+use crate::signals;
+use crate::statsd::StatsdClient;
+use crate::threads;
 
 const MONITOR_INTERVAL_SECS: u64 = 10;
+/// Default number of samples kept for [`dump_history`]
+pub const DEFAULT_SNAPSHOT_HISTORY: usize = 60;
+/// Default delay before the first sample, see [`MonitorOptions::warmup`]
+pub const DEFAULT_MONITOR_WARMUP: Duration = Duration::from_secs(1);
+/// How often the monitor checks for on-demand snapshot requests between samples
+const MONITOR_TICK_MS: u64 = 100;
+/// Shorter sampling intervals once mallinfo gets close to INT_MAX, as (threshold in
+/// percent of INT_MAX, interval), highest threshold first
+const ADAPTIVE_INTERVALS: [(f64, Duration); 3] = [
+    (95.0, Duration::from_millis(100)),
+    (80.0, Duration::from_secs(1)),
+    (50.0, Duration::from_secs(5)),
+];
+
+static PEAK_UORDBLKS: AtomicI32 = AtomicI32::new(0);
+static PEAK_RSS: AtomicU64 = AtomicU64::new(0);
+/// Reference for LAST_SAMPLE_MS, set on first use
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+/// When the monitor last took a sample, in ms since CLOCK_START
+static LAST_SAMPLE_MS: AtomicU64 = AtomicU64::new(0);
+/// Time until the next expected sample, in ms
+static EXPECTED_INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+/// The monitor is considered stale after this many missed intervals
+const STALE_INTERVALS: u64 = 3;
+/// Lower bound of the stale delay, so that a slow sample at the 100ms interval
+/// doesn't count as a dead monitor
+const MIN_STALE_MS: u64 = 5_000;
+/// Incremented on each (re)start, a monitor thread exits once it is superseded
+static MONITOR_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Whether memory reports outside the monitor (events, allocation counters) are logged
+/// as individual fields, see [`MonitorOptions::structured_logs`]
+static STRUCTURED_LOGS: AtomicBool = AtomicBool::new(true);
+/// Latest samples with their elapsed time since the monitor started, oldest first
+///
+/// Locked ignoring poisoning: a monitor that panicked while holding it is restarted by
+/// the supervisor, and the history is still wanted on shutdown.
+static HISTORY: Mutex<VecDeque<(Duration, MallinfoSnapshot)>> = Mutex::new(VecDeque::new());
+/// Aggregates of the running monitor, reset on each (re)start, see [`log_aggregates`]
+static AGGREGATES: Mutex<Option<Aggregates>> = Mutex::new(None);
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 struct MallInfo {
     arena: i32,
     ordblks: i32,
@@ -19,94 +71,1030 @@ struct MallInfo {
     keepcost: i32,
 }
 
+/// glibc >= 2.33 variant of mallinfo with size_t fields that can't wrap around
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct MallInfo2 {
+    arena: usize,
+    ordblks: usize,
+    smblks: usize,
+    hblks: usize,
+    hblkhd: usize,
+    usmblks: usize,
+    fsmblks: usize,
+    uordblks: usize,
+    fordblks: usize,
+    keepcost: usize,
+}
+
+/// Names of the mallinfo fields, in struct order
+pub const MALLINFO_FIELDS: [&str; 10] = [
+    "arena", "ordblks", "smblks", "hblks", "hblkhd", "usmblks", "fsmblks", "uordblks", "fordblks", "keepcost",
+];
+
+/// Fields checked for wraparound by default
+pub const DEFAULT_CHECKED_FIELDS: [&str; 4] = ["arena", "uordblks", "fordblks", "hblkhd"];
+
+impl MallInfo {
+    fn fields(&self) -> [(&'static str, i32); 10] {
+        [
+            ("arena", self.arena),
+            ("ordblks", self.ordblks),
+            ("smblks", self.smblks),
+            ("hblks", self.hblks),
+            ("hblkhd", self.hblkhd),
+            ("usmblks", self.usmblks),
+            ("fsmblks", self.fsmblks),
+            ("uordblks", self.uordblks),
+            ("fordblks", self.fordblks),
+            ("keepcost", self.keepcost),
+        ]
+    }
+}
+
+/// Parses a comma separated list of mallinfo field names, dropping repeated ones
+pub fn parse_field_list(input: &str) -> Result<Vec<&'static str>> {
+    if input.trim().is_empty() {
+        return Err(anyhow!(
+            "Empty mallinfo field list. Known fields: {}",
+            MALLINFO_FIELDS.join(", ")
+        ));
+    }
+    let mut fields = Vec::new();
+    for name in input.split(',') {
+        let name = name.trim();
+        let field = MALLINFO_FIELDS
+            .iter()
+            .find(|field| **field == name)
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown mallinfo field: '{}'. Known fields: {}",
+                    name,
+                    MALLINFO_FIELDS.join(", ")
+                )
+            })?;
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+    Ok(fields)
+}
+
 extern "C" {
     fn mallinfo() -> MallInfo;
 }
 
-#[derive(Debug, Clone, Copy)]
+type Mallinfo2Fn = unsafe extern "C" fn() -> MallInfo2;
+
+/// glibc's mallinfo2(), resolved at runtime: linking against it would keep the binary
+/// from starting at all before glibc 2.33, where plain mallinfo() wraps
+fn resolve_mallinfo2() -> Option<Mallinfo2Fn> {
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"mallinfo2".as_ptr()) };
+    (!symbol.is_null()).then(|| unsafe { std::mem::transmute::<*mut libc::c_void, Mallinfo2Fn>(symbol) })
+}
+
+/// Fails unless mallinfo2() is available, as --raw-mallinfo2 needs it
+pub fn check_mallinfo2() -> Result<()> {
+    resolve_mallinfo2()
+        .map(|_| ())
+        .ok_or_else(|| anyhow!("--raw-mallinfo2 requires mallinfo2(), which glibc only provides since 2.33"))
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
 struct MallinfoSnapshot {
     info: MallInfo,
 }
 
+/// Signed difference between two snapshots, in bytes
+#[derive(Debug, Clone, Copy)]
+struct MallinfoDelta {
+    arena: i64,
+    hblkhd: i64,
+    uordblks: i64,
+    fordblks: i64,
+    keepcost: i64,
+}
+
+/// Formats a byte count with the largest binary unit (B, KiB, MiB or GiB) that keeps
+/// it at least 1 after rounding, e.g. "512 B", "1.50 GiB"
+pub fn human_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes.unsigned_abs() < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Move up a unit as long as the value would be printed as 1024.00 or more
+    while unit + 1 < UNITS.len() && (value.abs() * 100.0).round() >= 102_400.0 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+/// [`human_bytes`] with an explicit sign, for deltas
+pub fn signed_human_bytes(bytes: i64) -> String {
+    if bytes >= 0 {
+        format!("+{}", human_bytes(bytes))
+    } else {
+        human_bytes(bytes)
+    }
+}
+
+impl MallinfoDelta {
+    /// Format the delta in a human-readable way
+    fn format_readable(&self) -> String {
+        format!(
+            "arena: {:+} ({})\n  hblkhd: {:+} ({})\n  uordblks: {:+} ({})\n  fordblks: {:+} ({})\n  keepcost: {:+} ({})",
+            self.arena,
+            signed_human_bytes(self.arena),
+            self.hblkhd,
+            signed_human_bytes(self.hblkhd),
+            self.uordblks,
+            signed_human_bytes(self.uordblks),
+            self.fordblks,
+            signed_human_bytes(self.fordblks),
+            self.keepcost,
+            signed_human_bytes(self.keepcost),
+        )
+    }
+}
+
 impl MallinfoSnapshot {
     fn new(info: MallInfo) -> Self {
         Self { info }
     }
 
-    /// Check if the mallinfo for invalid values
-    fn check_for_wraparound(&self) -> Vec<String> {
-        let mut warnings = Vec::new();
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse baseline {}", path.display()))
+    }
 
-        if self.info.arena < 0 {
-            warnings.push(format!("arena is negative: {} (integer overflow!)", self.info.arena));
+    fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Failed to write baseline {}", path.display()))
+    }
+
+    /// Single line summary of the fields that matter for the wraparound
+    fn format_compact(&self) -> String {
+        format!(
+            "arena {} hblkhd {} uordblks {} fordblks {} keepcost {}",
+            self.info.arena,
+            self.info.hblkhd,
+            self.info.uordblks,
+            self.info.fordblks,
+            self.info.keepcost,
+        )
+    }
+
+    /// How close the fields that overflow first are to INT_MAX
+    pub fn proximity(&self) -> ProximityReport {
+        // Unsigned so that a wrapped value shows as above 100%
+        let percent = |value: u64| value as f64 * 100.0 / i32::MAX as f64;
+        ProximityReport {
+            uordblks: percent(self.info.uordblks as u32 as u64),
+            arena_plus_hblkhd: percent(self.info.arena as u32 as u64 + self.info.hblkhd as u32 as u64),
         }
-        if self.info.uordblks < 0 {
-            warnings.push(format!("uordblks is negative: {} (integer overflow!)", self.info.uordblks));
+    }
+
+    /// Share of the heap that is free, fordblks / (uordblks + fordblks)
+    ///
+    /// The fields are read as unsigned since they wrap around past INT_MAX.
+    pub fn fragmentation(&self) -> f64 {
+        let free = self.info.fordblks as u32 as f64;
+        let total = self.info.uordblks as u32 as f64 + free;
+        if total == 0.0 {
+            0.0
+        } else {
+            free / total
         }
-        if self.info.fordblks < 0 {
-            warnings.push(format!("fordblks is negative: {} (integer overflow!)", self.info.fordblks));
+    }
+
+    /// Difference between this snapshot and a baseline (positive means growth)
+    fn diff_from_baseline(&self, baseline: &MallinfoSnapshot) -> MallinfoDelta {
+        MallinfoDelta {
+            arena: self.info.arena as i64 - baseline.info.arena as i64,
+            hblkhd: self.info.hblkhd as i64 - baseline.info.hblkhd as i64,
+            uordblks: self.info.uordblks as i64 - baseline.info.uordblks as i64,
+            fordblks: self.info.fordblks as i64 - baseline.info.fordblks as i64,
+            keepcost: self.info.keepcost as i64 - baseline.info.keepcost as i64,
         }
-        if self.info.hblkhd < 0 {
-            warnings.push(format!("hblkhd is negative: {} (integer overflow!)", self.info.hblkhd));
+    }
+
+    /// Check if the mallinfo for invalid values, only for the given fields
+    fn check_for_wraparound(&self, fields: &[&str]) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        for (name, value) in self.info.fields() {
+            if value < 0 && fields.contains(&name) {
+                warnings.push(format!("{} is negative: {} (integer overflow!)", name, value));
+            }
         }
 
         let arena_plus_hblkhd = self.info.arena as i64 + self.info.hblkhd as i64;
-        if arena_plus_hblkhd > i32::MAX as i64 {
+        if arena_plus_hblkhd > i32::MAX as i64 && (fields.contains(&"arena") || fields.contains(&"hblkhd")) {
             warnings.push(format!(
                 "arena + hblkhd > INT_MAX ({} + {} > {})",
                 self.info.arena, self.info.hblkhd, i32::MAX
             ));
         }
 
-        if self.info.uordblks > i32::MAX {
-            warnings.push(format!(
-                "uordblks > INT_MAX ({} > {})",
-                self.info.uordblks, i32::MAX
-            ));
-        }
-
         warnings
     }
 
     /// Format the mallinfo data in a human-readable way
     fn format_readable(&self) -> String {
         format!(
-            "arena: {} ({:.2} MB)\n  ordblks: {}\n  hblks: {}\n  hblkhd: {} ({:.2} MB)\n  uordblks: {} ({:.2} MB)\n  fordblks: {} ({:.2} MB)\n  keepcost: {} ({:.2} MB)",
+            "arena: {} ({})\n  ordblks: {}\n  hblks: {}\n  hblkhd: {} ({})\n  uordblks: {} ({})\n  fordblks: {} ({})\n  keepcost: {} ({})",
             self.info.arena,
-            self.info.arena as f64 / 1_048_576.0,
+            human_bytes(self.info.arena as i64),
             self.info.ordblks,
             self.info.hblks,
             self.info.hblkhd,
-            self.info.hblkhd as f64 / 1_048_576.0,
+            human_bytes(self.info.hblkhd as i64),
             self.info.uordblks,
-            self.info.uordblks as f64 / 1_048_576.0,
+            human_bytes(self.info.uordblks as i64),
             self.info.fordblks,
-            self.info.fordblks as f64 / 1_048_576.0,
+            human_bytes(self.info.fordblks as i64),
             self.info.keepcost,
-            self.info.keepcost as f64 / 1_048_576.0,
+            human_bytes(self.info.keepcost as i64),
         )
     }
 }
 
-/// Starts a background thread that reports mallinfo statistics every 10 seconds
-pub fn start_memory_monitor() {
-    thread::spawn(|| {
-        thread::sleep(Duration::from_secs(1));
-        let start_time = Instant::now();
-        info!("Memory monitor started - will report mallinfo() every {} seconds", MONITOR_INTERVAL_SECS);
+/// Fields as a percentage of INT_MAX
+#[derive(Debug, Clone, Copy)]
+pub struct ProximityReport {
+    pub uordblks: f64,
+    pub arena_plus_hblkhd: f64,
+}
 
-        loop {
-            let info = unsafe { mallinfo() };
-            let snapshot = MallinfoSnapshot::new(info);
+/// Allocated bytes as seen by mallinfo, and resident set size
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MemoryUsage {
+    pub uordblks: i32,
+    pub rss: Option<u64>,
+}
 
-            let elapsed = start_time.elapsed().as_secs();
+/// Sets whether memory reports are logged as individual fields or readable text (--human)
+pub fn set_structured_logs(enabled: bool) {
+    STRUCTURED_LOGS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn structured_logs() -> bool {
+    STRUCTURED_LOGS.load(Ordering::Relaxed)
+}
+
+fn clock_ms() -> u64 {
+    CLOCK_START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Records that the monitor is alive and when its next sample is expected
+fn mark_alive(next_sample_in: Duration) {
+    LAST_SAMPLE_MS.store(clock_ms(), Ordering::Relaxed);
+    EXPECTED_INTERVAL_MS.store(next_sample_in.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Time since the last sample if the monitor missed 3 intervals (and at least 5s), which
+/// means its thread died or is stuck
+pub fn staleness() -> Option<Duration> {
+    let since_last = clock_ms().saturating_sub(LAST_SAMPLE_MS.load(Ordering::Relaxed));
+    let interval = EXPECTED_INTERVAL_MS.load(Ordering::Relaxed);
+    let stale_after = (STALE_INTERVALS * interval).max(MIN_STALE_MS);
+    (since_last > stale_after).then(|| Duration::from_millis(since_last))
+}
+
+fn update_peaks(info: &MallInfo, rss: Option<u64>) {
+    PEAK_UORDBLKS.fetch_max(info.uordblks, Ordering::Relaxed);
+    if let Some(rss) = rss {
+        PEAK_RSS.fetch_max(rss, Ordering::Relaxed);
+    }
+}
+
+/// Samples memory usage outside of the monitor interval to refresh the peaks
+pub fn sample_peaks() {
+    let info = unsafe { mallinfo() };
+    update_peaks(&info, procfs::read_vm_rss());
+}
+
+pub fn current_usage() -> MemoryUsage {
+    let info = unsafe { mallinfo() };
+    MemoryUsage {
+        uordblks: info.uordblks,
+        rss: procfs::read_vm_rss(),
+    }
+}
+
+/// Highest memory usage seen so far
+pub fn peaks() -> MemoryUsage {
+    let rss = PEAK_RSS.load(Ordering::Relaxed);
+    MemoryUsage {
+        uordblks: PEAK_UORDBLKS.load(Ordering::Relaxed),
+        rss: (rss > 0).then_some(rss),
+    }
+}
+
+/// Running min/mean/max of a sampled value, in bytes
+#[derive(Debug, Default)]
+struct Stats {
+    count: u64,
+    sum: i128,
+    min: i64,
+    max: i64,
+}
+
+impl Stats {
+    fn add(&mut self, value: i64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value as i128;
+    }
+
+    fn min(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    fn mean(&self) -> Option<i64> {
+        (self.count > 0).then(|| (self.sum / self.count as i128) as i64)
+    }
+
+    fn max(&self) -> Option<i64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    fn format_readable(&self) -> String {
+        match (self.min(), self.mean(), self.max()) {
+            (Some(min), Some(mean), Some(max)) => format!(
+                "min {}, mean {}, max {}",
+                human_bytes(min),
+                human_bytes(mean),
+                human_bytes(max)
+            ),
+            _ => "n/a".to_string(),
+        }
+    }
+}
+
+/// Mean of the latest `window` values
+#[derive(Debug)]
+struct MovingAverage {
+    window: usize,
+    values: VecDeque<i64>,
+    sum: i64,
+}
+
+impl MovingAverage {
+    fn new(window: usize) -> Self {
+        Self {
+            window,
+            values: VecDeque::with_capacity(window),
+            sum: 0,
+        }
+    }
+
+    fn add(&mut self, value: i64) -> i64 {
+        self.values.push_back(value);
+        self.sum += value;
+        if self.values.len() > self.window {
+            self.sum -= self.values.pop_front().unwrap_or_default();
+        }
+        self.sum / self.values.len() as i64
+    }
+}
+
+/// Moving averages of uordblks and arena + hblkhd, see [`MonitorOptions::smooth`]
+#[derive(Debug)]
+struct Smoothing {
+    uordblks: MovingAverage,
+    arena_plus_hblkhd: MovingAverage,
+}
+
+/// Aggregates of every sample taken by the monitor
+#[derive(Debug, Default)]
+struct Aggregates {
+    uordblks: Stats,
+    arena: Stats,
+    rss: Stats,
+    max_fragmentation: f64,
+}
+
+impl Aggregates {
+    fn add(&mut self, snapshot: &MallinfoSnapshot, rss: Option<u64>) {
+        // Unsigned as in proximity(), a wrapped sample would drag min and mean negative
+        self.uordblks.add(snapshot.info.uordblks as u32 as i64);
+        self.arena.add(snapshot.info.arena as u32 as i64);
+        if let Some(rss) = rss {
+            self.rss.add(rss as i64);
+        }
+        self.max_fragmentation = self.max_fragmentation.max(snapshot.fragmentation());
+    }
+
+    fn format_readable(&self) -> String {
+        format!(
+            "uordblks: {}\n  arena: {}\n  rss: {}\n  fragmentation: max {:.1}%",
+            self.uordblks.format_readable(),
+            self.arena.format_readable(),
+            self.rss.format_readable(),
+            self.max_fragmentation * 100.0,
+        )
+    }
+}
+
+/// Logs the min/mean/max of the samples taken since the monitor last (re)started
+pub fn log_aggregates() {
+    let aggregates = AGGREGATES.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(aggregates) = aggregates.as_ref().filter(|aggregates| aggregates.uordblks.count > 0)
+    else {
+        return;
+    };
+    if structured_logs() {
+        info!(
+            samples = aggregates.uordblks.count,
+            uordblks_min = aggregates.uordblks.min(),
+            uordblks_mean = aggregates.uordblks.mean(),
+            uordblks_max = aggregates.uordblks.max(),
+            arena_min = aggregates.arena.min(),
+            arena_mean = aggregates.arena.mean(),
+            arena_max = aggregates.arena.max(),
+            rss_min = aggregates.rss.min(),
+            rss_mean = aggregates.rss.mean(),
+            rss_max = aggregates.rss.max(),
+            max_fragmentation = aggregates.max_fragmentation,
+            "Mallinfo aggregates"
+        );
+    } else {
+        info!(
+            "Aggregates over {} samples:\n  {}",
+            aggregates.uordblks.count,
+            aggregates.format_readable()
+        );
+    }
+}
+
+/// Logs the latest samples, to show the lead-up to a fatal exit
+pub fn dump_history() {
+    let history = HISTORY.lock().unwrap_or_else(PoisonError::into_inner);
+    if history.is_empty() {
+        return;
+    }
+    if structured_logs() {
+        for (elapsed, snapshot) in history.iter() {
+            warn!(
+                elapsed_secs = elapsed.as_secs_f64(),
+                arena = snapshot.info.arena,
+                hblkhd = snapshot.info.hblkhd,
+                uordblks = snapshot.info.uordblks,
+                fordblks = snapshot.info.fordblks,
+                keepcost = snapshot.info.keepcost,
+                "Mallinfo history sample"
+            );
+        }
+    } else {
+        warn!("Last {} mallinfo samples:\n  {}", history.len(), format_history(&history));
+    }
+}
+
+fn format_history(history: &VecDeque<(Duration, MallinfoSnapshot)>) -> String {
+    let lines: Vec<String> = history
+        .iter()
+        .map(|(elapsed, snapshot)| {
+            format!("[{:>6.1}s] {}", elapsed.as_secs_f64(), snapshot.format_compact())
+        })
+        .collect();
+    lines.join("\n  ")
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitorOptions {
+    /// Delay before the first sample
+    pub warmup: Duration,
+    /// If it points to an existing file, it is loaded as the baseline snapshot and each
+    /// report also includes the delta from it. Otherwise the first sample is written to
+    /// that path and becomes the baseline.
+    pub baseline_path: Option<PathBuf>,
+    /// Also report /proc/self/smaps_rollup (Pss, Private_Dirty, Swap)
+    pub detailed_mem: bool,
+    /// Also report the memory of the CEF subprocesses, which mallinfo doesn't see
+    pub web_mem: bool,
+    /// Also report the thread and open file descriptor counts
+    pub resource_counts: bool,
+    /// Also report the unclamped mallinfo2() values from a dedicated thread
+    pub raw_mallinfo2: bool,
+    /// Cores the monitor thread is pinned to
+    pub pin_cpu: Option<Vec<usize>>,
+    /// Exit as soon as a wraparound condition is detected
+    pub fail_on_overflow: bool,
+    /// mallinfo fields checked for wraparound
+    pub checked_fields: Vec<&'static str>,
+    /// Number of latest samples logged on a fatal exit
+    pub snapshot_history: usize,
+    /// Log the samples as individual fields, otherwise as readable blocks (--human)
+    pub structured_logs: bool,
+    /// Also push every sample as StatsD gauges to this "host:port"
+    pub statsd_addr: Option<String>,
+    /// Pick the sampling interval from the mean of this many samples instead of the
+    /// latest one, to ignore short spikes. The wraparound checks always use the raw
+    /// values: a wrapped field is not noise, and averaging it with the earlier samples
+    /// would delay or hide the very overflow --fail-on-overflow has to catch.
+    pub smooth: Option<usize>,
+}
+
+struct Monitor {
+    fail_on_overflow: bool,
+    checked_fields: Vec<&'static str>,
+    baseline_path: Option<PathBuf>,
+    baseline: Option<MallinfoSnapshot>,
+    detailed_mem: bool,
+    web_mem: bool,
+    resource_counts: bool,
+    snapshot_history: usize,
+    previous_rollup: Option<SmapsRollup>,
+    previous_keepcost: Option<i32>,
+    structured_logs: bool,
+    statsd: Option<StatsdClient>,
+    smoothing: Option<Smoothing>,
+    /// Highest of uordblks and arena + hblkhd in the latest sample, in percent of INT_MAX
+    usage_percent: f64,
+    start_time: Instant,
+}
+
+impl Monitor {
+    fn record_history(&self, snapshot: MallinfoSnapshot) {
+        if self.snapshot_history == 0 {
+            return;
+        }
+        let mut history = HISTORY.lock().unwrap_or_else(PoisonError::into_inner);
+        history.push_back((self.start_time.elapsed(), snapshot));
+        while history.len() > self.snapshot_history {
+            history.pop_front();
+        }
+        // Formatted ahead of time since the crash handler can't allocate
+        signals::set_crash_context(&format!(
+            "Last {} mallinfo samples:\n  {}\n",
+            history.len(),
+            format_history(&history)
+        ));
+    }
+
+    /// Time until the next periodic sample, shorter as the usage approaches INT_MAX
+    fn interval(&self) -> Duration {
+        ADAPTIVE_INTERVALS
+            .iter()
+            .find(|(threshold, _)| self.usage_percent >= *threshold)
+            .map(|(_, interval)| *interval)
+            .unwrap_or(Duration::from_secs(MONITOR_INTERVAL_SECS))
+    }
+
+    /// Reads and reports mallinfo (and the optional extra statistics) once
+    fn sample_once(&mut self) {
+        let info = unsafe { mallinfo() };
+        let snapshot = MallinfoSnapshot::new(info);
+        self.record_history(snapshot);
+        let rss = procfs::read_vm_rss();
+        update_peaks(&info, rss);
+        if let Some(aggregates) = AGGREGATES.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            aggregates.add(&snapshot, rss);
+        }
+        // Unsigned as in proximity(), so that a wrapped field keeps the fastest interval
+        // instead of dropping back to the slowest one right at the overflow
+        let uordblks = info.uordblks as u32 as i64;
+        let arena_plus_hblkhd = info.arena as u32 as i64 + info.hblkhd as u32 as i64;
+        let usage = match &mut self.smoothing {
+            Some(smoothing) => {
+                let uordblks = smoothing.uordblks.add(uordblks);
+                let arena_plus_hblkhd = smoothing.arena_plus_hblkhd.add(arena_plus_hblkhd);
+                let samples = smoothing.uordblks.values.len();
+                if self.structured_logs {
+                    info!(samples, uordblks, arena_plus_hblkhd, "Smoothed usage");
+                } else {
+                    info!(
+                        "Smoothed over {} samples: uordblks {}, arena + hblkhd {}",
+                        samples,
+                        human_bytes(uordblks),
+                        human_bytes(arena_plus_hblkhd)
+                    );
+                }
+                uordblks.max(arena_plus_hblkhd)
+            }
+            None => uordblks.max(arena_plus_hblkhd),
+        };
+        self.usage_percent = usage as f64 * 100.0 / i32::MAX as f64;
+
+        let elapsed = self.start_time.elapsed().as_secs();
+        if self.structured_logs {
+            info!(
+                elapsed_secs = elapsed,
+                arena = info.arena,
+                ordblks = info.ordblks,
+                smblks = info.smblks,
+                hblks = info.hblks,
+                hblkhd = info.hblkhd,
+                usmblks = info.usmblks,
+                fsmblks = info.fsmblks,
+                uordblks = info.uordblks,
+                fordblks = info.fordblks,
+                keepcost = info.keepcost,
+                rss = rss,
+                "Mallinfo sample"
+            );
+        } else {
             info!("Mallinfo (elapsed time: {}s):\n  {}", elapsed, snapshot.format_readable());
-            let warnings = snapshot.check_for_wraparound();
-            for warning in warnings {
-                warn!("{}", warning);
+        }
+
+        let proximity = snapshot.proximity();
+        if self.structured_logs {
+            info!(
+                uordblks_percent = proximity.uordblks,
+                arena_hblkhd_percent = proximity.arena_plus_hblkhd,
+                "INT_MAX proximity"
+            );
+        } else {
+            info!(
+                "uordblks {:.0}% arena+hblkhd {:.0}% of INT_MAX",
+                proximity.uordblks, proximity.arena_plus_hblkhd
+            );
+        }
+        if let Some(statsd) = &mut self.statsd {
+            let mut gauges: Vec<(String, f64)> = info
+                .fields()
+                .iter()
+                .map(|(name, value)| (format!("mallinfo.{}", name), *value as f64))
+                .collect();
+            if let Some(rss) = rss {
+                gauges.push(("rss".to_string(), rss as f64));
+            }
+            gauges.push(("mallinfo.fragmentation".to_string(), snapshot.fragmentation()));
+            statsd.send_gauges(&gauges);
+        }
+
+        // A free share that keeps rising along with arena means the heap fragments
+        let fragmentation = snapshot.fragmentation();
+        let keepcost_delta = self
+            .previous_keepcost
+            .map_or(0, |previous| info.keepcost as i64 - previous as i64);
+        self.previous_keepcost = Some(info.keepcost);
+        if self.structured_logs {
+            info!(
+                fragmentation = fragmentation,
+                keepcost_delta = keepcost_delta,
+                "Fragmentation"
+            );
+        } else {
+            info!(
+                "Fragmentation: {:.1}% of the heap is free (keepcost {:+} since the last sample)",
+                fragmentation * 100.0,
+                keepcost_delta
+            );
+        }
+
+        match &self.baseline {
+            Some(baseline) => {
+                let delta = snapshot.diff_from_baseline(baseline);
+                if self.structured_logs {
+                    info!(
+                        arena = delta.arena,
+                        hblkhd = delta.hblkhd,
+                        uordblks = delta.uordblks,
+                        fordblks = delta.fordblks,
+                        keepcost = delta.keepcost,
+                        "Mallinfo delta from baseline"
+                    );
+                } else {
+                    info!("Mallinfo delta from baseline:\n  {}", delta.format_readable());
+                }
+            }
+            None => {
+                if let Some(path) = &self.baseline_path {
+                    match snapshot.save(path) {
+                        Ok(()) => info!("Saved mallinfo baseline to {}", path.display()),
+                        Err(e) => warn!("{:#}", e),
+                    }
+                    self.baseline = Some(snapshot);
+                }
+            }
+        }
+
+        match SmapsRollup::read() {
+            Some(rollup) => {
+                // Growing anonymous memory along with uordblks is the leak, file-backed
+                // growth is usually just the page cache
+                if self.structured_logs {
+                    let (anonymous_delta, file_backed_delta) = match &self.previous_rollup {
+                        Some(previous) => rollup.rss_split_delta(previous),
+                        None => (None, None),
+                    };
+                    info!(
+                        anonymous = rollup.anonymous,
+                        file_backed = rollup.file_backed(),
+                        anonymous_delta,
+                        file_backed_delta,
+                        "RSS split"
+                    );
+                } else {
+                    info!(
+                        "RSS split:\n  {}",
+                        rollup.format_rss_split(self.previous_rollup.as_ref())
+                    );
+                }
+                if self.detailed_mem {
+                    if self.structured_logs {
+                        info!(
+                            rss = rollup.rss,
+                            anonymous = rollup.anonymous,
+                            pss = rollup.pss,
+                            private_dirty = rollup.private_dirty,
+                            swap = rollup.swap,
+                            "smaps_rollup"
+                        );
+                    } else {
+                        info!("smaps_rollup:\n  {}", rollup.format_readable());
+                    }
+                }
+                self.previous_rollup = Some(rollup);
+            }
+            None => {
+                if self.detailed_mem {
+                    warn!("Failed to read /proc/self/smaps_rollup");
+                }
+            }
+        }
+
+        if self.resource_counts {
+            let threads = procfs::count_threads();
+            let fds = procfs::count_fds();
+            if self.structured_logs {
+                info!(threads = threads, fds = fds, "Resource counts");
+            } else {
+                let format = |count: Option<usize>| {
+                    count.map_or("n/a".to_string(), |count| count.to_string())
+                };
+                info!("Threads: {}, open fds: {}", format(threads), format(fds));
+            }
+        }
+
+        if self.web_mem {
+            match DescendantsRss::read() {
+                Some(descendants) if self.structured_logs => info!(
+                    count = descendants.count,
+                    rss = descendants.rss,
+                    "CEF subprocesses"
+                ),
+                Some(descendants) => info!("CEF subprocesses: {}", descendants.format_readable()),
+                None => warn!("Failed to scan /proc for subprocesses"),
+            }
+        }
+
+        #[cfg(feature = "alloc-counters")]
+        crate::alloc_counter::log_report();
+
+        let warnings = snapshot.check_for_wraparound(&self.checked_fields);
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+        if self.fail_on_overflow && !warnings.is_empty() {
+            error!("mallinfo wraparound detected, exiting (--fail-on-overflow)");
+            shutdown::shutdown(ExitReason::OverflowAbort);
+        }
+    }
+}
+
+/// Starts a new monitor after the previous one stopped sampling, see [`staleness`]
+///
+/// The aggregates start over and a saved baseline is loaded again.
+pub fn restart_memory_monitor(options: &MonitorOptions) -> Result<()> {
+    start_memory_monitor(&MonitorOptions {
+        warmup: Duration::ZERO,
+        raw_mallinfo2: false,
+        ..options.clone()
+    })
+}
+
+/// Starts a background thread that reports mallinfo statistics every 10 seconds, down
+/// to every 100ms as the usage approaches INT_MAX
+///
+/// A SIGUSR1 triggers an additional snapshot right away.
+pub fn start_memory_monitor(options: &MonitorOptions) -> Result<()> {
+    let MonitorOptions {
+        warmup,
+        baseline_path,
+        detailed_mem,
+        web_mem,
+        resource_counts,
+        raw_mallinfo2,
+        pin_cpu,
+        fail_on_overflow,
+        checked_fields,
+        snapshot_history,
+        structured_logs,
+        statsd_addr,
+        smooth,
+    } = options.clone();
+
+    let baseline = match &baseline_path {
+        Some(path) if path.exists() => {
+            let snapshot = MallinfoSnapshot::load(path)?;
+            info!("Loaded mallinfo baseline from {}", path.display());
+            Some(snapshot)
+        }
+        _ => None,
+    };
+
+    let statsd = match &statsd_addr {
+        Some(addr) => Some(StatsdClient::connect(addr)?),
+        None => None,
+    };
+
+    if raw_mallinfo2 {
+        start_mallinfo2_reader(warmup);
+    }
+
+    *AGGREGATES.lock().unwrap_or_else(PoisonError::into_inner) = Some(Aggregates::default());
+    let generation = MONITOR_GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    mark_alive(warmup + Duration::from_secs(MONITOR_INTERVAL_SECS));
+    threads::spawn("mem_monitor", move || {
+        if let Some(cores) = &pin_cpu {
+            affinity::pin_current_thread(cores, "memory monitor");
+        }
+
+        thread::sleep(warmup);
+        let mut monitor = Monitor {
+            fail_on_overflow,
+            checked_fields,
+            baseline_path,
+            baseline,
+            detailed_mem,
+            web_mem,
+            resource_counts,
+            snapshot_history,
+            previous_rollup: None,
+            previous_keepcost: None,
+            structured_logs,
+            statsd,
+            smoothing: smooth.map(|window| Smoothing {
+                uordblks: MovingAverage::new(window),
+                arena_plus_hblkhd: MovingAverage::new(window),
+            }),
+            usage_percent: 0.0,
+            start_time: Instant::now(),
+        };
+        info!(
+            "Memory monitor started - will report mallinfo() every {} seconds, more often close to INT_MAX",
+            MONITOR_INTERVAL_SECS
+        );
+
+        let mut signal_count = signals::sigusr1_count();
+        let mut interval = monitor.interval();
+        loop {
+            if MONITOR_GENERATION.load(Ordering::Relaxed) != generation {
+                warn!("Memory monitor was restarted while stuck, stopping the old one");
+                break;
+            }
+            monitor.sample_once();
+
+            let next_interval = monitor.interval();
+            if next_interval != interval {
+                interval = next_interval;
+                info!(
+                    "Memory at {:.1}% of INT_MAX, sampling every {:?}",
+                    monitor.usage_percent, interval
+                );
+            }
+            mark_alive(interval);
+            let next_sample = Instant::now() + interval;
+            while Instant::now() < next_sample {
+                thread::sleep(Duration::from_millis(MONITOR_TICK_MS));
+
+                let count = signals::sigusr1_count();
+                if count != signal_count {
+                    signal_count = count;
+                    info!("========== On-demand snapshot (SIGUSR1) ==========");
+                    monitor.sample_once();
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Starts a background thread reporting the true 64-bit mallinfo2() values
+///
+/// With the override loaded, mallinfo() is clamped to what CEF can handle; this shows
+/// the actual allocator state next to it.
+fn start_mallinfo2_reader(warmup: Duration) {
+    let Some(mallinfo2) = resolve_mallinfo2() else {
+        warn!("mallinfo2() is unavailable, not reporting the unclamped values");
+        return;
+    };
+    threads::spawn("mallinfo2", move || {
+        thread::sleep(warmup);
+        info!("mallinfo2 reader started - will report mallinfo2() every {} seconds", MONITOR_INTERVAL_SECS);
+
+        loop {
+            let info = unsafe { mallinfo2() };
+            if structured_logs() {
+                info!(
+                    arena = info.arena as u64,
+                    hblkhd = info.hblkhd as u64,
+                    uordblks = info.uordblks as u64,
+                    fordblks = info.fordblks as u64,
+                    "Mallinfo2 sample"
+                );
+            } else {
+                let arena_plus_hblkhd = info.arena as u64 + info.hblkhd as u64;
+                info!(
+                    "Mallinfo2 (unclamped):\n  arena: {} ({})\n  hblkhd: {} ({})\n  arena + hblkhd: {} ({:.1}% of INT_MAX)\n  uordblks: {} ({:.1}% of INT_MAX)\n  fordblks: {} ({})",
+                    info.arena,
+                    human_bytes(info.arena as i64),
+                    info.hblkhd,
+                    human_bytes(info.hblkhd as i64),
+                    arena_plus_hblkhd,
+                    arena_plus_hblkhd as f64 * 100.0 / i32::MAX as f64,
+                    info.uordblks,
+                    info.uordblks as f64 * 100.0 / i32::MAX as f64,
+                    info.fordblks,
+                    human_bytes(info.fordblks as i64),
+                );
             }
 
             thread::sleep(Duration::from_secs(MONITOR_INTERVAL_SECS));
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_field_list_valid() {
+        assert_eq!(parse_field_list("arena").unwrap(), vec!["arena"]);
+        assert_eq!(
+            parse_field_list(" arena , uordblks ").unwrap(),
+            vec!["arena", "uordblks"]
+        );
+    }
+
+    #[test]
+    fn parse_field_list_unknown_field() {
+        assert!(parse_field_list("arena,heap").is_err());
+        assert!(parse_field_list("Arena").is_err());
+        assert!(parse_field_list("arena,").is_err());
+    }
+
+    #[test]
+    fn parse_field_list_duplicates() {
+        assert_eq!(
+            parse_field_list("uordblks,arena,uordblks").unwrap(),
+            vec!["uordblks", "arena"]
+        );
+    }
+
+    #[test]
+    fn parse_field_list_empty() {
+        assert!(parse_field_list("").is_err());
+        assert!(parse_field_list("  ").is_err());
+        assert!(parse_field_list(",").is_err());
+    }
+
+    #[test]
+    fn human_bytes_boundaries() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1024), "1.00 KiB");
+        // Would round to "1024.00 KiB"
+        assert_eq!(human_bytes(1_048_575), "1.00 MiB");
+        assert_eq!(human_bytes(1_048_576), "1.00 MiB");
+        assert_eq!(human_bytes(1_073_741_824), "1.00 GiB");
+        assert_eq!(human_bytes(i32::MAX as i64), "2.00 GiB");
+    }
+
+    #[test]
+    fn human_bytes_negative() {
+        assert_eq!(human_bytes(-1), "-1 B");
+        assert_eq!(human_bytes(-1023), "-1023 B");
+        assert_eq!(human_bytes(-1024), "-1.00 KiB");
+        assert_eq!(human_bytes(i32::MIN as i64), "-2.00 GiB");
+    }
+
+    #[test]
+    fn signed_human_bytes_always_has_a_sign() {
+        assert_eq!(signed_human_bytes(0), "+0 B");
+        assert_eq!(signed_human_bytes(1023), "+1023 B");
+        assert_eq!(signed_human_bytes(1_048_576), "+1.00 MiB");
+        assert_eq!(signed_human_bytes(i32::MAX as i64), "+2.00 GiB");
+        assert_eq!(signed_human_bytes(-1024), "-1.00 KiB");
+        assert_eq!(signed_human_bytes(-1_048_575), "-1.00 MiB");
+    }
+}
@@ -1,66 +1,517 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use compositor_pipeline::error::RegisterOutputError;
 use compositor_pipeline::pipeline::output::*;
-use compositor_pipeline::pipeline::{OutputVideoOptions, PipelineOutputEndCondition, RegisterOutputOptions};
+use compositor_pipeline::pipeline::{
+    OutputVideoOptions, PipelineEvent, PipelineOutputEndCondition, RegisterOutputOptions,
+};
 use compositor_pipeline::Pipeline;
 use compositor_render::scene::Component;
-use compositor_render::{OutputId, Resolution};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tracing::info;
+use compositor_render::{Frame, FrameData, OutputId, Resolution};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
 
+use crate::config::Config;
+use crate::error::SmelterCrashError;
+use crate::events::{self, Event};
+use crate::frame_hash::{FrameHashLog, HashAlgorithm};
+use crate::shm::ShmWriter;
+use crate::shutdown::{self, ExitReason};
+use crate::stall::StallDetector;
+use crate::tcp::TcpFrameWriter;
+use crate::{affinity, signals, snapshot, threads};
+
+/// Default id of the raw output
+pub const DEFAULT_OUTPUT_ID: &str = "output";
+
+/// Warn if the first frame takes longer than this to arrive
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Incremented on each registration of the raw output
+static OUTPUT_GENERATION: AtomicU64 = AtomicU64::new(0);
+/// Latest output generation unregistered on purpose, so that its consumers stopping on
+/// the disconnect leave the shutdown to whoever unregistered it
+static CLOSED_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Call before unregistering the output on purpose, e.g. as part of a planned shutdown
+pub fn mark_output_closing() {
+    CLOSED_GENERATION.store(OUTPUT_GENERATION.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+fn is_output_closing(generation: u64) -> bool {
+    CLOSED_GENERATION.load(Ordering::Relaxed) >= generation
+}
+
+/// How the raw output frames are consumed
+#[derive(Debug, Clone, Default)]
+pub struct ConsumerOptions {
+    /// Publish frames to this POSIX shared memory segment instead of dropping them
+    pub shm_name: Option<String>,
+    /// Cap how many frames per second are drained from the output
+    pub consume_rate: Option<f64>,
+    /// Write the next frame as a PNG to this path whenever SIGUSR2 is received
+    pub snapshot_path: Option<PathBuf>,
+    /// Cores the consumer threads are pinned to
+    pub pin_cpu: Option<Vec<usize>>,
+    /// Warn about runs of frozen or black frames
+    pub detect_stall_frames: bool,
+    /// Number of threads draining the output
+    pub consumers: usize,
+    /// Stream frames to this "host:port" over TCP instead of dropping them
+    pub tcp_addr: Option<String>,
+    /// Log a hash of every frame to this file
+    pub hash_frames: Option<(HashAlgorithm, PathBuf)>,
+    /// Log the PTS of the first and then every Nth frame
+    pub show_pts: Option<u64>,
+    /// Stop the run once this many frames were received
+    pub max_frames: Option<u64>,
+    /// Id the raw output is registered with
+    pub output_id: String,
+}
+
+/// Resolution and pixel format of the frames actually produced by the output
+#[derive(Debug, Clone, Copy)]
+pub struct FrameFormat {
+    pub resolution: Resolution,
+    pub pixel_format: &'static str,
+}
+
+impl FrameFormat {
+    fn of(frame: &Frame) -> Self {
+        let pixel_format = match &frame.data {
+            FrameData::PlanarYuv420(_) => "yuv420p",
+            FrameData::PlanarYuvJ420(_) => "yuvj420p",
+            FrameData::InterleavedYuv422(_) => "uyvy422",
+            _ => "other",
+        };
+        Self {
+            resolution: frame.resolution,
+            pixel_format,
+        }
+    }
+}
+
+/// Statistics collected by the frame consumer
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    frames: AtomicU64,
+    first_frame_format: OnceLock<FrameFormat>,
+    /// Time between consecutive frames, only collected when requested since it grows with the run
+    intervals: Option<Mutex<Vec<Duration>>>,
+    recv_errors: AtomicU64,
+    last_recv_error: Mutex<Option<&'static str>>,
+    time_to_first_frame: OnceLock<Duration>,
+}
+
+impl FrameStats {
+    fn new(collect_intervals: bool) -> Self {
+        Self {
+            frames: AtomicU64::new(0),
+            first_frame_format: OnceLock::new(),
+            intervals: collect_intervals.then(|| Mutex::new(Vec::new())),
+            recv_errors: AtomicU64::new(0),
+            last_recv_error: Mutex::new(None),
+            time_to_first_frame: OnceLock::new(),
+        }
+    }
+
+    fn record_recv_error(&self, reason: &'static str) {
+        self.recv_errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_recv_error.lock().unwrap() = Some(reason);
+    }
+
+    fn record_frame(&self, interval: Option<Duration>) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        if let (Some(intervals), Some(interval)) = (&self.intervals, interval) {
+            intervals.lock().unwrap().push(interval);
+        }
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Format of the first received frame, if any
+    pub fn frame_format(&self) -> Option<FrameFormat> {
+        self.first_frame_format.get().copied()
+    }
+
+    /// Time from the pipeline start to the first received frame, if any
+    pub fn time_to_first_frame(&self) -> Option<Duration> {
+        self.time_to_first_frame.get().copied()
+    }
+
+    /// Total number of failed receives and the reason of the last one
+    pub fn recv_errors(&self) -> (u64, Option<&'static str>) {
+        (
+            self.recv_errors.load(Ordering::Relaxed),
+            *self.last_recv_error.lock().unwrap(),
+        )
+    }
+
+    pub fn intervals(&self) -> Vec<Duration> {
+        match &self.intervals {
+            Some(intervals) => intervals.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+pub struct RawOutput {
+    pub output_id: OutputId,
+    pub stats: Arc<FrameStats>,
+    /// Receives once when --max-frames frames were received
+    pub max_frames_reached: Option<mpsc::Receiver<()>>,
+}
+
+/// Paces a loop to a target rate, compensating for the time spent in each iteration
+struct RateLimiter {
+    interval: Duration,
+    next_deadline: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / rate),
+            next_deadline: Instant::now(),
+        }
+    }
+
+    fn wait(&mut self) {
+        let now = Instant::now();
+        if self.next_deadline > now {
+            std::thread::sleep(self.next_deadline - now);
+            self.next_deadline += self.interval;
+        } else {
+            // Running behind: don't try to catch up with a burst
+            self.next_deadline = now + self.interval;
+        }
+    }
+}
+
+/// Logs frame PTS at a fixed frame interval and warns when it goes backwards
+///
+/// With several consumer threads frames can be handled slightly out of order, so
+/// use a single consumer when checking monotonicity.
+struct PtsLog {
+    every: u64,
+    frames: u64,
+    last_pts: Option<Duration>,
+}
+
+impl PtsLog {
+    fn new(every: u64) -> Self {
+        Self {
+            every,
+            frames: 0,
+            last_pts: None,
+        }
+    }
+
+    fn record(&mut self, pts: Duration) {
+        if let Some(last_pts) = self.last_pts {
+            if pts <= last_pts {
+                warn!(
+                    "Frame {} PTS {:?} is not after the previous PTS {:?}",
+                    self.frames, pts, last_pts
+                );
+            }
+        }
+        if self.frames.is_multiple_of(self.every) {
+            info!("Frame {} PTS {:?}", self.frames, pts);
+        }
+        self.last_pts = Some(pts);
+        self.frames += 1;
+    }
+}
+
+/// [`FrameSink::last_frame_ns`] before the first frame
+const NO_FRAME: u64 = u64::MAX;
+
+/// Per-frame work, shared by all the consumer threads
+struct FrameSink {
+    resolution: Resolution,
+    pipeline_started: Instant,
+    stats: Arc<FrameStats>,
+    /// When the latest frame arrived, in ns since `pipeline_started`, [`NO_FRAME`]
+    /// before the first one. Atomic so that the consumers don't serialize on it.
+    last_frame_ns: AtomicU64,
+    stall_detector: Option<Mutex<StallDetector>>,
+    snapshot_path: Option<PathBuf>,
+    snapshot_signal_count: AtomicU64,
+    shm: Option<Mutex<ShmWriter>>,
+    /// Reset to None once the connection is closed, frames are dropped from then on
+    tcp: Option<Mutex<Option<TcpFrameWriter>>>,
+    hash_log: Option<Mutex<FrameHashLog>>,
+    pts_log: Option<Mutex<PtsLog>>,
+    max_frames: Option<u64>,
+    /// Taken by the consumer that receives the last frame of --max-frames
+    max_frames_reached: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl FrameSink {
+    fn handle_frame(&self, frame: Frame) {
+        let elapsed = self.pipeline_started.elapsed();
+        let last_frame_ns = self
+            .last_frame_ns
+            .swap(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        if last_frame_ns == NO_FRAME {
+            info!("Time to first frame: {:?}", elapsed);
+            let _ = self.stats.time_to_first_frame.set(elapsed);
+            events::record(Event::FirstFrame);
+            check_first_frame(&frame, self.resolution, &self.stats);
+        }
+        // Another consumer may have stored a later time in between, hence the saturation
+        self.stats.record_frame((last_frame_ns != NO_FRAME).then(|| {
+            elapsed.saturating_sub(Duration::from_nanos(last_frame_ns))
+        }));
+        if self.max_frames.is_some_and(|max| self.stats.frame_count() >= max) {
+            if let Some(sender) = self.max_frames_reached.lock().unwrap().take() {
+                let _ = sender.send(());
+            }
+        }
+
+        if let Some(pts_log) = &self.pts_log {
+            pts_log.lock().unwrap().record(frame.pts);
+        }
+
+        if let Some(stall_detector) = &self.stall_detector {
+            stall_detector.lock().unwrap().check(&frame);
+        }
+
+        if let Some(path) = &self.snapshot_path {
+            let count = signals::sigusr2_count();
+            // Only the consumer that wins the swap takes the snapshot
+            if self.snapshot_signal_count.swap(count, Ordering::Relaxed) != count {
+                snapshot::spawn_save_png(frame.clone(), path.clone());
+            }
+        }
+        if let Some(shm) = &self.shm {
+            shm.lock().unwrap().write_frame(&frame);
+        }
+        if let Some(hash_log) = &self.hash_log {
+            hash_log.lock().unwrap().write_frame(&frame);
+        }
+
+        if let Some(tcp) = &self.tcp {
+            let mut tcp = tcp.lock().unwrap();
+            if let Some(writer) = tcp.as_mut() {
+                if let Err(e) = writer.write_frame(&frame) {
+                    warn!("TCP output to {} closed ({}), dropping frames from now on", writer.addr(), e);
+                    *tcp = None;
+                }
+            }
+        }
+    }
+}
+
+/// `pipeline_started` is when the pipeline was started, to measure the time to the
+/// first frame
 pub fn setup_raw_output(
     pipeline: &Arc<Mutex<Pipeline>>,
     scene: Component,
-    resolution: Resolution,
-) -> Result<OutputId> {
-    let output_id = OutputId(Arc::from("output"));
-    let receiver = Pipeline::register_raw_data_output(
-        pipeline,
-        output_id.clone(),
-        RegisterOutputOptions {
-            output_options: RawDataOutputOptions {
-                video: Some(RawVideoOptions { resolution }),
+    config: &Config,
+    pipeline_started: Instant,
+) -> Result<RawOutput, SmelterCrashError> {
+    register_raw_output(pipeline, scene, config, pipeline_started)
+        .map_err(SmelterCrashError::OutputSetup)
+}
+
+fn register_raw_output(
+    pipeline: &Arc<Mutex<Pipeline>>,
+    scene: Component,
+    config: &Config,
+    pipeline_started: Instant,
+) -> Result<RawOutput> {
+    let resolution = config.resolution;
+    let consumer = config.consumer.clone();
+    let output_id = OutputId(Arc::from(consumer.output_id.as_str()));
+
+    // Open the frame destinations first, so a failure doesn't leave a registered
+    // output that nobody drains
+    let shm = match consumer.shm_name {
+        Some(name) => Some(Mutex::new(ShmWriter::create(&name, resolution)?)),
+        None => None,
+    };
+
+    let tcp = match &consumer.tcp_addr {
+        Some(addr) => Some(TcpFrameWriter::connect(addr)?),
+        None => None,
+    };
+
+    let hash_log = match &consumer.hash_frames {
+        Some((algorithm, path)) => Some(Mutex::new(FrameHashLog::create(*algorithm, path)?)),
+        None => None,
+    };
+
+    let register = || {
+        Pipeline::register_raw_data_output(
+            pipeline,
+            output_id.clone(),
+            RegisterOutputOptions {
+                output_options: RawDataOutputOptions {
+                    video: Some(RawVideoOptions { resolution }),
+                    audio: None,
+                },
+                video: Some(OutputVideoOptions {
+                    initial: scene.clone(),
+                    end_condition: PipelineOutputEndCondition::Never,
+                }),
                 audio: None,
             },
-            video: Some(OutputVideoOptions {
-                initial: scene,
-                end_condition: PipelineOutputEndCondition::Never,
-            }),
-            audio: None,
-        },
-    )?;
-
-    // Spawn thread to consume frames as fast as possible
+        )
+    };
+    let receiver = match register() {
+        Ok(receiver) => receiver,
+        // The id is taken, e.g. by a previous run in this process: replace that output
+        // rather than failing
+        Err(RegisterOutputError::AlreadyRegistered(_)) => {
+            warn!("Output id '{}' was already registered, replacing that output", output_id.0);
+            // Its consumers stop on the disconnect, they must not end the run
+            mark_output_closing();
+            pipeline
+                .lock()
+                .unwrap()
+                .unregister_output(&output_id)
+                .map_err(|e| anyhow!("Failed to unregister output '{}': {}", output_id.0, e))?;
+            events::record(Event::OutputUnregistered {
+                output_id: output_id.0.to_string(),
+            });
+            register()
+                .map_err(|e| anyhow!("Failed to register output '{}': {}", output_id.0, e))?
+        }
+        Err(e) => return Err(anyhow!("Failed to register output '{}': {}", output_id.0, e)),
+    };
+    let generation = OUTPUT_GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    events::record(Event::OutputRegistered {
+        output_id: output_id.0.to_string(),
+    });
+
+    // The rate is shared between the consumers, each one drains its part of it
+    let consumers = consumer.consumers.max(1);
+    let consume_rate = consumer.consume_rate.map(|rate| {
+        info!("Throttling frame consumer to {} fps", rate);
+        rate / consumers as f64
+    });
+
+    if let Some(path) = &consumer.snapshot_path {
+        info!("Send SIGUSR2 to save the next output frame to {}", path.display());
+    }
+
+    let (max_frames_sender, max_frames_receiver) = match consumer.max_frames {
+        Some(_) => {
+            let (sender, receiver) = mpsc::channel();
+            (Some(sender), Some(receiver))
+        }
+        None => (None, None),
+    };
+
+    let stats = Arc::new(FrameStats::new(config.bench.is_some() || consumer.max_frames.is_some()));
+    let sink = Arc::new(FrameSink {
+        resolution,
+        pipeline_started,
+        stats: stats.clone(),
+        last_frame_ns: AtomicU64::new(NO_FRAME),
+        stall_detector: consumer
+            .detect_stall_frames
+            .then(|| Mutex::new(StallDetector::new())),
+        snapshot_path: consumer.snapshot_path,
+        snapshot_signal_count: AtomicU64::new(signals::sigusr2_count()),
+        shm,
+        tcp: tcp.map(|writer| Mutex::new(Some(writer))),
+        hash_log,
+        pts_log: consumer.show_pts.map(|every| Mutex::new(PtsLog::new(every))),
+        max_frames: consumer.max_frames,
+        max_frames_reached: Mutex::new(max_frames_sender),
+    });
+
+    // Spawn threads to consume frames as fast as possible (or at the requested rate)
     if let Some(video_receiver) = receiver.video {
-        std::thread::Builder::new()
-            .name("frame_consumer".to_string())
-            .spawn(move || {
-                let mut consecutive_errors = 0u64;
+        if consumers > 1 {
+            info!("Draining the output with {} consumer threads", consumers);
+        }
+        let running = Arc::new(AtomicUsize::new(consumers));
+        for _ in 0..consumers {
+            let running = running.clone();
+            let video_receiver = video_receiver.clone();
+            let sink = sink.clone();
+            let pin_cpu = consumer.pin_cpu.clone();
+            let mut rate_limiter = consume_rate.map(RateLimiter::new);
+            threads::spawn("frame_consumer", move || {
+                if let Some(cores) = &pin_cpu {
+                    affinity::pin_current_thread(cores, "frame consumer");
+                }
 
                 // Simply receive and let frames drop immediately - no storage, no batching
+                // (unless they are published to shared memory or sent over TCP)
                 loop {
+                    if let Some(rate_limiter) = &mut rate_limiter {
+                        rate_limiter.wait();
+                    }
                     match video_receiver.recv() {
-                        Ok(_frame) => {
-                            consecutive_errors = 0;
+                        Ok(PipelineEvent::Data(frame)) => sink.handle_frame(frame),
+                        // Also sent when the output is unregistered on purpose, which
+                        // shuts down on its own (e.g. --max-frames with its report)
+                        Ok(PipelineEvent::EOS) if is_output_closing(generation) => break,
+                        Ok(PipelineEvent::EOS) => {
+                            info!("Output reached its end condition, stopping");
+                            shutdown::shutdown(ExitReason::OutputEnded);
                         }
                         Err(e) => {
-                            consecutive_errors += 1;
-                            info!("Frame consumer recv error #{}: {:?}", consecutive_errors, e);
-                            if consecutive_errors > 10 {
-                                info!("Too many consecutive errors, exiting consumer thread");
-                                break;
-                            }
-                            std::thread::sleep(Duration::from_millis(10));
+                            // recv() blocks until a frame arrives so it never times out, the
+                            // only failure is the pipeline dropping the output, which is
+                            // final. Frames stopping without any error means a stall instead.
+                            sink.stats.record_recv_error("disconnected");
+                            info!("Output channel disconnected ({:?}), exiting consumer thread", e);
+                            break;
                         }
                     }
                 }
-            })
-            .expect("Failed to spawn frame consumer thread");
+
+                // Nothing drains the output anymore, running on would only hang
+                let last = running.fetch_sub(1, Ordering::AcqRel) == 1;
+                if last && !is_output_closing(generation) {
+                    error!("All frame consumers stopped, the output was dropped by the pipeline");
+                    shutdown::shutdown(ExitReason::Error);
+                }
+            });
+        }
     } else {
         info!("Warning: No video receiver available for raw output");
     }
 
+    let watched_stats = stats.clone();
+    threads::spawn("first_frame", move || {
+        std::thread::sleep(FIRST_FRAME_TIMEOUT);
+        if watched_stats.time_to_first_frame().is_none() {
+            warn!("No output frame received {:?} after the output registration", FIRST_FRAME_TIMEOUT);
+        }
+    });
+
     info!("Started raw output (running indefinitely)");
 
-    Ok(output_id)
+    Ok(RawOutput {
+        output_id,
+        stats,
+        max_frames_reached: max_frames_receiver,
+    })
+}
+
+/// Logs the format of the first frame and warns if it isn't what was requested
+fn check_first_frame(frame: &Frame, expected: Resolution, stats: &FrameStats) {
+    let format = FrameFormat::of(frame);
+    info!(
+        "First frame: {}x{} {}",
+        format.resolution.width, format.resolution.height, format.pixel_format
+    );
+    if format.resolution != expected {
+        warn!(
+            "Output frame resolution {}x{} differs from the configured {}x{}",
+            format.resolution.width, format.resolution.height, expected.width, expected.height
+        );
+    }
+    let _ = stats.first_frame_format.set(format);
 }
@@ -0,0 +1,197 @@
+use std::fs;
+
+/// Memory summary from /proc/self/smaps_rollup, in bytes
+///
+/// Fields are optional since older kernels don't report all of them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmapsRollup {
+    pub rss: Option<u64>,
+    /// Part of the Rss not backed by a file (heap, stacks, anonymous mappings)
+    pub anonymous: Option<u64>,
+    pub pss: Option<u64>,
+    pub private_dirty: Option<u64>,
+    pub swap: Option<u64>,
+}
+
+impl SmapsRollup {
+    pub fn read() -> Option<Self> {
+        let content = fs::read_to_string("/proc/self/smaps_rollup").ok()?;
+        Some(Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut rollup = Self::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(bytes) = parse_kb(value) else {
+                continue;
+            };
+            match key.trim() {
+                "Rss" => rollup.rss = Some(bytes),
+                "Anonymous" => rollup.anonymous = Some(bytes),
+                "Pss" => rollup.pss = Some(bytes),
+                "Private_Dirty" => rollup.private_dirty = Some(bytes),
+                "Swap" => rollup.swap = Some(bytes),
+                _ => {}
+            }
+        }
+        rollup
+    }
+
+    /// Part of the Rss backed by files (page cache of mapped libraries and files)
+    pub fn file_backed(&self) -> Option<u64> {
+        Some(self.rss?.saturating_sub(self.anonymous?))
+    }
+
+    /// Anonymous and file-backed Rss, with their change since `previous`
+    pub fn format_rss_split(&self, previous: Option<&SmapsRollup>) -> String {
+        let (anonymous_delta, file_backed_delta) = match previous {
+            Some(previous) => self.rss_split_delta(previous),
+            None => (None, None),
+        };
+        let delta = |delta: Option<i64>| match delta {
+            Some(delta) => format!(" ({:+.2} MB)", delta as f64 / 1_048_576.0),
+            None => String::new(),
+        };
+        format!(
+            "anonymous: {}{}\n  file-backed: {}{}",
+            format_mb(self.anonymous),
+            delta(anonymous_delta),
+            format_mb(self.file_backed()),
+            delta(file_backed_delta),
+        )
+    }
+
+    /// Change of the anonymous and file-backed memory since `previous`, in bytes
+    pub fn rss_split_delta(&self, previous: &SmapsRollup) -> (Option<i64>, Option<i64>) {
+        let delta = |current: Option<u64>, previous: Option<u64>| Some(current? as i64 - previous? as i64);
+        (
+            delta(self.anonymous, previous.anonymous),
+            delta(self.file_backed(), previous.file_backed()),
+        )
+    }
+
+    /// Format the rollup in a human-readable way
+    pub fn format_readable(&self) -> String {
+        format!(
+            "Rss: {}\n  Anonymous: {}\n  Pss: {}\n  Private_Dirty: {}\n  Swap: {}",
+            format_mb(self.rss),
+            format_mb(self.anonymous),
+            format_mb(self.pss),
+            format_mb(self.private_dirty),
+            format_mb(self.swap),
+        )
+    }
+}
+
+/// Resident set size from /proc/self/status, in bytes
+pub fn read_vm_rss() -> Option<u64> {
+    read_status_rss("/proc/self/status")
+}
+
+fn read_status_rss(path: &str) -> Option<u64> {
+    let content = fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(parse_kb)
+}
+
+/// Memory of the processes spawned by us (directly or not), such as the CEF helpers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DescendantsRss {
+    pub count: usize,
+    /// Sum of their VmRSS, in bytes
+    pub rss: u64,
+}
+
+impl DescendantsRss {
+    /// Scans /proc for the descendants of this process
+    pub fn read() -> Option<Self> {
+        let mut parents = Vec::new();
+        for entry in fs::read_dir("/proc").ok()?.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            // Processes may exit while scanning
+            if let Some(ppid) = read_ppid(pid) {
+                parents.push((pid, ppid));
+            }
+        }
+
+        let mut descendants = vec![std::process::id()];
+        let mut i = 0;
+        while i < descendants.len() {
+            let parent = descendants[i];
+            descendants.extend(
+                parents
+                    .iter()
+                    .filter(|(_, ppid)| *ppid == parent)
+                    .map(|(pid, _)| *pid),
+            );
+            i += 1;
+        }
+
+        let mut result = Self::default();
+        for pid in &descendants[1..] {
+            // Kernel threads and zombies have no VmRSS
+            if let Some(rss) = read_status_rss(&format!("/proc/{}/status", pid)) {
+                result.count += 1;
+                result.rss += rss;
+            }
+        }
+        Some(result)
+    }
+
+    pub fn format_readable(&self) -> String {
+        format!("{}, total RSS {}", self.count, format_mb(Some(self.rss)))
+    }
+}
+
+/// Parent pid from /proc/<pid>/stat
+fn read_ppid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name is in parentheses and may itself contain spaces or parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Number of threads of this process, from /proc/self/task
+pub fn count_threads() -> Option<usize> {
+    count_dir_entries("/proc/self/task")
+}
+
+/// Number of open file descriptors, from /proc/self/fd
+pub fn count_fds() -> Option<usize> {
+    // The directory handle used to list it is itself an fd, don't count it
+    count_dir_entries("/proc/self/fd").map(|count| count.saturating_sub(1))
+}
+
+fn count_dir_entries(path: &str) -> Option<usize> {
+    Some(fs::read_dir(path).ok()?.count())
+}
+
+/// Total physical memory from /proc/meminfo, in bytes
+pub fn read_mem_total() -> Option<u64> {
+    let content = fs::read_to_string("/proc/meminfo").ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(parse_kb)
+}
+
+/// Parses a "1234 kB" value into bytes
+fn parse_kb(value: &str) -> Option<u64> {
+    let kb: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn format_mb(bytes: Option<u64>) -> String {
+    match bytes {
+        Some(bytes) => format!("{:.2} MB", bytes as f64 / 1_048_576.0),
+        None => "n/a".to_string(),
+    }
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use std::path::Path;
+
+// Both features replace the global allocator. alloc-counters takes precedence so that
+// --all-features still builds, dhat profiling is then unavailable.
+#[cfg(all(feature = "dhat-heap", not(feature = "alloc-counters")))]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+#[cfg(all(feature = "dhat-heap", not(feature = "alloc-counters")))]
+static PROFILER: std::sync::Mutex<Option<dhat::Profiler>> = std::sync::Mutex::new(None);
+
+/// Starts dhat heap profiling, the profile is written to `path` by [`finish`]
+#[cfg(all(feature = "dhat-heap", not(feature = "alloc-counters")))]
+pub fn start(path: &Path) -> Result<()> {
+    let profiler = dhat::Profiler::builder().file_name(path).build();
+    *PROFILER.lock().unwrap() = Some(profiler);
+    tracing::info!("Heap profiling enabled, profile will be written to {}", path.display());
+    Ok(())
+}
+
+#[cfg(not(all(feature = "dhat-heap", not(feature = "alloc-counters"))))]
+pub fn start(_path: &Path) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "--profile requires building with `--features dhat-heap` (and without alloc-counters)"
+    ))
+}
+
+/// Writes the heap profile, if profiling was started. Must be called before exiting
+/// the process since the profiler is never dropped otherwise.
+pub fn finish() {
+    #[cfg(all(feature = "dhat-heap", not(feature = "alloc-counters")))]
+    drop(PROFILER.lock().unwrap().take());
+}
@@ -3,10 +3,20 @@ use std::thread;
 use std::time::Duration;
 use tracing::info;
 
-pub fn allocate_and_hold(ram_size: String) -> Result<()> {
-    let bytes = parse_memory_size(&ram_size)?;
+use crate::{procfs, signals, threads};
 
-    thread::spawn(move || {
+/// Default share of the physical memory --ram may request without --force-ram
+pub const DEFAULT_MAX_PERCENT: f64 = 90.0;
+
+/// How often the holder checks for a release request
+const RELEASE_POLL_MS: u64 = 100;
+
+/// Allocates and touches `bytes` of RAM on a dedicated thread, and holds it forever,
+/// or until a SIGUSR1 is received with `release_on_sigusr1`
+pub fn allocate_and_hold(bytes: usize, release_on_sigusr1: bool) -> Result<()> {
+    let ram_size = format!("{:.2} MB", bytes as f64 / 1_048_576.0);
+
+    threads::spawn("ram_hold", move || {
         info!("Allocating {} of RAM...", ram_size);
         let mut memory: Vec<u8> = vec![0; bytes];
 
@@ -16,18 +26,53 @@ pub fn allocate_and_hold(ram_size: String) -> Result<()> {
             memory[i] = 1;
         }
 
-        info!("Allocated {} of RAM, holding indefinitely", ram_size);
+        if !release_on_sigusr1 {
+            info!("Allocated {} of RAM, holding indefinitely", ram_size);
+            loop {
+                thread::sleep(Duration::from_secs(3600));
+            }
+        }
+        info!("Allocated {} of RAM, holding until SIGUSR1", ram_size);
 
-        // Keep the memory allocated forever
-        loop {
-            thread::sleep(Duration::from_secs(3600));
+        // Keep the memory allocated until asked to release it. SIGUSR1 also triggers a
+        // memory snapshot, taken independently of the release.
+        let signal_count = signals::sigusr1_count();
+        while signals::sigusr1_count() == signal_count {
+            thread::sleep(Duration::from_millis(RELEASE_POLL_MS));
         }
+
+        drop(memory);
+        let trimmed = unsafe { libc::malloc_trim(0) } != 0;
+        info!(
+            "Released {} of RAM (SIGUSR1), malloc_trim {}; send SIGUSR1 again for a snapshot",
+            ram_size,
+            if trimmed { "returned memory to the system" } else { "had nothing to return" }
+        );
     });
 
     Ok(())
 }
 
-fn parse_memory_size(input: &str) -> Result<usize> {
+/// Refuses sizes above `max_percent` of the physical memory, which would get the
+/// process (or others) OOM-killed rather than reproduce anything
+pub fn check_physical_memory(bytes: usize, max_percent: f64) -> Result<()> {
+    // Without /proc/meminfo there is nothing to compare against
+    let Some(total) = procfs::read_mem_total() else {
+        return Ok(());
+    };
+    let limit = total as f64 * max_percent / 100.0;
+    if bytes as f64 > limit {
+        return Err(anyhow!(
+            "--ram {:.2} GB exceeds {}% of the physical memory ({:.2} GB). Pass --force-ram to allocate it anyway",
+            bytes as f64 / 1_073_741_824.0,
+            max_percent,
+            total as f64 / 1_073_741_824.0
+        ));
+    }
+    Ok(())
+}
+
+pub fn parse_memory_size(input: &str) -> Result<usize> {
     let input = input.trim().to_uppercase();
 
     // Find where the number ends and the unit begins
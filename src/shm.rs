@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use compositor_render::{Frame, FrameData, Resolution};
+use std::ffi::CString;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use tracing::{info, warn};
+
+/// Shared memory layout (all integers native-endian):
+///
+/// Segment header, 64 bytes:
+///   0: magic u32 ("SMSH"), 4: version u32, 8: slot count u32,
+///   16: slot data capacity u64, 24: latest complete sequence u64 (0 = none yet)
+///
+/// Then SLOT_COUNT slots, each a 32 bytes header followed by the frame data:
+///   0: sequence u64 (0 while being written), 8: width u32, 12: height u32,
+///   16: format u32, 20: data length u32
+///
+/// Frame `n` is written to slot `n % SLOT_COUNT`. A reader:
+///   1. Acquire-loads the latest sequence `n` and the slot sequence, skipping the slot
+///      unless it equals `n`
+///   2. copies the slot header and data
+///   3. issues an Acquire fence and re-loads the slot sequence, discarding the copy
+///      (torn frame) if it changed
+const MAGIC: u32 = 0x4853_4d53;
+const VERSION: u32 = 1;
+const SLOT_COUNT: usize = 2;
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 32;
+const LATEST_SEQUENCE_OFFSET: usize = 24;
+
+/// Planar YUV 4:2:0, Y then U then V planes
+pub const FORMAT_YUV420P: u32 = 1;
+
+pub struct ShmWriter {
+    name: CString,
+    ptr: *mut u8,
+    len: usize,
+    slot_capacity: usize,
+    sequence: u64,
+    logged_unsupported: bool,
+}
+
+// The mapping is owned exclusively by the writer, which is only used by one thread at a time
+unsafe impl Send for ShmWriter {}
+
+impl ShmWriter {
+    /// Creates (or truncates) the POSIX shared memory segment sized for YUV420 frames
+    pub fn create(name: &str, resolution: Resolution) -> Result<Self> {
+        let c_name = CString::new(name).map_err(|_| anyhow!("Invalid shared memory name: {}", name))?;
+        let frame_size = resolution.width * resolution.height * 3 / 2;
+        // Keep every slot 8-byte aligned for the atomic sequence field
+        let slot_capacity = frame_size.div_ceil(8) * 8;
+        let len = HEADER_SIZE + SLOT_COUNT * (SLOT_HEADER_SIZE + slot_capacity);
+
+        let ptr = unsafe {
+            let fd = libc::shm_open(c_name.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o600);
+            if fd < 0 {
+                return Err(anyhow!(
+                    "shm_open({}) failed: {}",
+                    name,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            if libc::ftruncate(fd, len as libc::off_t) != 0 {
+                let err = std::io::Error::last_os_error();
+                libc::close(fd);
+                return Err(anyhow!("ftruncate({}) failed: {}", name, err));
+            }
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED {
+                return Err(anyhow!(
+                    "mmap({}) failed: {}",
+                    name,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            ptr as *mut u8
+        };
+
+        let writer = Self {
+            name: c_name,
+            ptr,
+            len,
+            slot_capacity,
+            sequence: 0,
+            logged_unsupported: false,
+        };
+        unsafe {
+            std::ptr::write_bytes(writer.ptr, 0, writer.len);
+            writer.write_u32(0, MAGIC);
+            writer.write_u32(4, VERSION);
+            writer.write_u32(8, SLOT_COUNT as u32);
+            writer.write_u64(16, slot_capacity as u64);
+        }
+
+        info!("Publishing frames to shared memory {} ({} bytes)", name, len);
+        Ok(writer)
+    }
+
+    /// Copies the frame into the next slot and publishes it as the latest one
+    pub fn write_frame(&mut self, frame: &Frame) {
+        let planes = match &frame.data {
+            FrameData::PlanarYuv420(planes) => planes,
+            _ => {
+                if !self.logged_unsupported {
+                    self.logged_unsupported = true;
+                    warn!("Unsupported frame format for shared memory output, frames are not published");
+                }
+                return;
+            }
+        };
+
+        let data_len = planes.y_plane.len() + planes.u_plane.len() + planes.v_plane.len();
+        if data_len > self.slot_capacity {
+            if !self.logged_unsupported {
+                self.logged_unsupported = true;
+                warn!(
+                    "Frame of {} bytes does not fit the shared memory slot ({} bytes)",
+                    data_len, self.slot_capacity
+                );
+            }
+            return;
+        }
+
+        self.sequence += 1;
+        let slot = HEADER_SIZE + (self.sequence as usize % SLOT_COUNT) * (SLOT_HEADER_SIZE + self.slot_capacity);
+        unsafe {
+            self.atomic_u64(slot).store(0, Ordering::Release);
+            // The Release store alone doesn't keep the writes below from becoming visible
+            // before the 0, which would let a reader accept a torn frame
+            fence(Ordering::Release);
+            self.write_u32(slot + 8, frame.resolution.width as u32);
+            self.write_u32(slot + 12, frame.resolution.height as u32);
+            self.write_u32(slot + 16, FORMAT_YUV420P);
+            self.write_u32(slot + 20, data_len as u32);
+
+            let mut offset = slot + SLOT_HEADER_SIZE;
+            for plane in [&planes.y_plane, &planes.u_plane, &planes.v_plane] {
+                std::ptr::copy_nonoverlapping(plane.as_ptr(), self.ptr.add(offset), plane.len());
+                offset += plane.len();
+            }
+
+            fence(Ordering::Release);
+            self.atomic_u64(slot).store(self.sequence, Ordering::Release);
+            self.atomic_u64(LATEST_SEQUENCE_OFFSET).store(self.sequence, Ordering::Release);
+        }
+    }
+
+    unsafe fn write_u32(&self, offset: usize, value: u32) {
+        (self.ptr.add(offset) as *mut u32).write(value);
+    }
+
+    unsafe fn write_u64(&self, offset: usize, value: u64) {
+        (self.ptr.add(offset) as *mut u64).write(value);
+    }
+
+    unsafe fn atomic_u64(&self, offset: usize) -> &AtomicU64 {
+        &*(self.ptr.add(offset) as *const AtomicU64)
+    }
+}
+
+impl Drop for ShmWriter {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            libc::shm_unlink(self.name.as_ptr());
+        }
+    }
+}
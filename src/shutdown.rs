@@ -0,0 +1,111 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info};
+
+use crate::events::{self, Event};
+use crate::{memory_monitor, profile, signals, threads};
+
+/// How often the signal watcher checks for SIGINT/SIGTERM
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Why the process is exiting, each one with its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The --bench duration elapsed
+    DurationElapsed,
+    /// --max-frames frames were received
+    MaxFrames,
+    /// The pipeline ended the raw output
+    OutputEnded,
+    /// The run returned on its own: `list-gpus` or the event loop finished
+    Completed,
+    /// --max-runtime was reached
+    MaxRuntime,
+    /// Interrupted by SIGINT or SIGTERM
+    Signal(i32),
+    /// A mallinfo wraparound was detected with --fail-on-overflow
+    OverflowAbort,
+    /// Setup or the event loop failed
+    Error,
+}
+
+impl ExitReason {
+    /// Name used in the logs and the shutdown event
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitReason::DurationElapsed => "duration_elapsed",
+            ExitReason::MaxFrames => "max_frames",
+            ExitReason::OutputEnded => "output_ended",
+            ExitReason::Completed => "completed",
+            ExitReason::MaxRuntime => "max_runtime",
+            ExitReason::Signal(_) => "signal",
+            ExitReason::OverflowAbort => "overflow",
+            ExitReason::Error => "error",
+        }
+    }
+
+    /// 0 for the runs that ended as planned, 128 + the signal number like shells do
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ExitReason::DurationElapsed
+            | ExitReason::MaxFrames
+            | ExitReason::OutputEnded
+            | ExitReason::Completed => 0,
+            ExitReason::Error => 1,
+            ExitReason::MaxRuntime => 3,
+            ExitReason::OverflowAbort => 4,
+            ExitReason::Signal(signal) => 128 + signal,
+        }
+    }
+
+    fn is_failure(self) -> bool {
+        matches!(self, ExitReason::MaxRuntime | ExitReason::OverflowAbort | ExitReason::Error)
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitReason::Signal(signal) => write!(f, "signal {}", signal),
+            reason => f.write_str(reason.name()),
+        }
+    }
+}
+
+/// Single exit point: logs the sample aggregates and the reason, records it, writes the
+/// heap profile and exits with the reason's exit code
+///
+/// Only the first caller shuts down, threads reaching their own exit condition
+/// meanwhile park forever: exit() racing itself isn't safe in glibc.
+pub fn shutdown(reason: ExitReason) -> ! {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        loop {
+            std::thread::park();
+        }
+    }
+    memory_monitor::log_aggregates();
+    if reason.is_failure() {
+        memory_monitor::dump_history();
+        error!("Exiting: {} (exit code {})", reason, reason.exit_code());
+    } else {
+        info!("Exiting: {} (exit code {})", reason, reason.exit_code());
+    }
+    events::record(Event::ShutdownReason {
+        reason: reason.name().to_string(),
+    });
+    profile::finish();
+    std::process::exit(reason.exit_code());
+}
+
+/// Shuts down through [`shutdown`] on SIGINT or SIGTERM
+pub fn start_signal_watch() {
+    threads::spawn("signal_watch", || loop {
+        std::thread::sleep(SIGNAL_POLL);
+        if let Some(signal) = signals::termination_signal() {
+            shutdown(ExitReason::Signal(signal));
+        }
+    });
+}
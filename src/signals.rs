@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use tracing::{info, warn};
+
+static SIGUSR1_COUNT: AtomicU64 = AtomicU64::new(0);
+static SIGUSR2_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Latest SIGINT or SIGTERM received, 0 if none
+static TERMINATION_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static CRASH_HANDLERS_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    // Only touch an atomic here to stay async-signal-safe
+    match signal {
+        libc::SIGUSR1 => SIGUSR1_COUNT.fetch_add(1, Ordering::Relaxed),
+        libc::SIGUSR2 => SIGUSR2_COUNT.fetch_add(1, Ordering::Relaxed),
+        libc::SIGINT | libc::SIGTERM => {
+            TERMINATION_SIGNAL.store(signal, Ordering::Relaxed);
+            0
+        }
+        _ => 0,
+    };
+}
+
+/// SIGINT or SIGTERM, once one was received
+pub fn termination_signal() -> Option<i32> {
+    match TERMINATION_SIGNAL.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Installs the SIGUSR1/SIGUSR2 handlers used to request on-demand actions, the
+/// SIGINT/SIGTERM ones requesting a shutdown, and the SIGILL/SIGSEGV handlers
+/// reporting native crashes
+pub fn install() -> Result<()> {
+    for (signal, name) in [
+        (libc::SIGUSR1, "SIGUSR1"),
+        (libc::SIGUSR2, "SIGUSR2"),
+        (libc::SIGINT, "SIGINT"),
+        (libc::SIGTERM, "SIGTERM"),
+    ] {
+        set_handler(signal, name, handle_signal, libc::SA_RESTART)?;
+    }
+
+    // The first backtrace() call loads libgcc, which allocates: do it now rather than
+    // in the crash handler
+    let mut frames = [std::ptr::null_mut(); 1];
+    unsafe { backtrace(frames.as_mut_ptr(), 1) };
+    install_crash_handlers()
+}
+
+/// Installs the SIGILL/SIGSEGV handlers reporting native crashes, logging the
+/// handlers they replace
+///
+/// Call it again once CEF is initialized: Chromium may install its own crash handlers
+/// over these, and the native crash this tool is about would then go unreported.
+pub fn install_crash_handlers() -> Result<()> {
+    let reinstall = CRASH_HANDLERS_INSTALLED.swap(true, Ordering::Relaxed);
+    for (signal, name) in [(libc::SIGILL, "SIGILL"), (libc::SIGSEGV, "SIGSEGV")] {
+        let previous = current_handler(signal);
+        if previous == handle_crash as *const () as libc::sighandler_t {
+            continue;
+        }
+        if reinstall {
+            warn!("{} handler was replaced by {:#x}, reinstalling the crash handler", name, previous);
+        } else if previous != libc::SIG_DFL && previous != libc::SIG_IGN {
+            info!("Replacing the previous {} handler {:#x} with the crash handler", name, previous);
+        }
+        // Run on the alternate stack Rust sets up, so a stack overflow is reported too,
+        // and go back to the default action to still get a core dump
+        set_handler(signal, name, handle_crash, libc::SA_ONSTACK | libc::SA_RESETHAND)?;
+    }
+    Ok(())
+}
+
+/// Handler currently installed for `signal`, SIG_DFL if it can't be read
+fn current_handler(signal: libc::c_int) -> libc::sighandler_t {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(signal, std::ptr::null(), &mut action) != 0 {
+            return libc::SIG_DFL;
+        }
+        action.sa_sigaction
+    }
+}
+
+fn set_handler(
+    signal: libc::c_int,
+    name: &str,
+    handler: extern "C" fn(libc::c_int),
+    flags: libc::c_int,
+) -> Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = flags;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(anyhow!(
+                "Failed to install {} handler: {}",
+                name,
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    Ok(())
+}
+
+extern "C" {
+    fn backtrace(buffer: *mut *mut libc::c_void, size: libc::c_int) -> libc::c_int;
+    fn backtrace_symbols_fd(buffer: *const *mut libc::c_void, size: libc::c_int, fd: libc::c_int);
+}
+
+const CRASH_CONTEXT_SIZE: usize = 16 * 1024;
+const CRASH_BACKTRACE_FRAMES: usize = 64;
+
+/// Text written by the crash handler after the backtrace, kept in a static buffer
+/// since the handler can't allocate or take locks
+struct CrashContext {
+    busy: AtomicBool,
+    len: AtomicUsize,
+    buffer: UnsafeCell<[u8; CRASH_CONTEXT_SIZE]>,
+}
+
+// The buffer is only accessed by whoever flips `busy`
+unsafe impl Sync for CrashContext {}
+
+static CRASH_CONTEXT: CrashContext = CrashContext {
+    busy: AtomicBool::new(false),
+    len: AtomicUsize::new(0),
+    buffer: UnsafeCell::new([0; CRASH_CONTEXT_SIZE]),
+};
+
+/// Replaces the context printed if the process crashes, truncated to 16 KiB
+pub fn set_crash_context(text: &str) {
+    if CRASH_CONTEXT.busy.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let len = text.len().min(CRASH_CONTEXT_SIZE);
+    unsafe { (&mut *CRASH_CONTEXT.buffer.get())[..len].copy_from_slice(&text.as_bytes()[..len]) };
+    CRASH_CONTEXT.len.store(len, Ordering::Relaxed);
+    CRASH_CONTEXT.busy.store(false, Ordering::Release);
+}
+
+fn write_stderr(bytes: &[u8]) {
+    unsafe { libc::write(libc::STDERR_FILENO, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+}
+
+/// Only uses async-signal-safe calls: write(2), backtrace_symbols_fd and raise
+extern "C" fn handle_crash(signal: libc::c_int) {
+    write_stderr(match signal {
+        libc::SIGILL => b"\n==== Fatal signal SIGILL (illegal instruction) ====\n" as &[u8],
+        _ => b"\n==== Fatal signal SIGSEGV (segmentation fault) ====\n",
+    });
+
+    let mut frames = [std::ptr::null_mut(); CRASH_BACKTRACE_FRAMES];
+    unsafe {
+        let count = backtrace(frames.as_mut_ptr(), CRASH_BACKTRACE_FRAMES as libc::c_int);
+        backtrace_symbols_fd(frames.as_ptr(), count, libc::STDERR_FILENO);
+    }
+
+    // Skipped if the monitor was updating it when the crash happened
+    if !CRASH_CONTEXT.busy.swap(true, Ordering::Acquire) {
+        let len = CRASH_CONTEXT.len.load(Ordering::Relaxed);
+        write_stderr(unsafe { &(&*CRASH_CONTEXT.buffer.get())[..len] });
+    }
+
+    // The handler was reset to the default action on entry, which dumps core
+    unsafe { libc::raise(signal) };
+}
+
+/// Number of SIGUSR1 received so far
+///
+/// Each interested thread keeps the last value it saw and reacts when it changes,
+/// so several of them can watch the same signal independently.
+pub fn sigusr1_count() -> u64 {
+    SIGUSR1_COUNT.load(Ordering::Relaxed)
+}
+
+/// Number of SIGUSR2 received so far, see [`sigusr1_count`]
+pub fn sigusr2_count() -> u64 {
+    SIGUSR2_COUNT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Context, Result};
+use compositor_render::{Frame, FrameData};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::threads;
+
+/// Converts the frame to RGB and writes it as a PNG on a separate thread,
+/// so the frame consumer is not blocked by the encoding
+pub fn spawn_save_png(frame: Frame, path: PathBuf) {
+    threads::spawn("snapshot_png", move || match save_png(&frame, &path) {
+        Ok(()) => info!("Saved output snapshot to {}", path.display()),
+        Err(e) => warn!("Failed to save output snapshot: {:#}", e),
+    });
+}
+
+fn save_png(frame: &Frame, path: &Path) -> Result<()> {
+    let planes = match &frame.data {
+        FrameData::PlanarYuv420(planes) => planes,
+        _ => return Err(anyhow!("Unsupported frame format for snapshot")),
+    };
+
+    let width = frame.resolution.width;
+    let height = frame.resolution.height;
+    let rgb = yuv420_to_rgb(&planes.y_plane, &planes.u_plane, &planes.v_plane, width, height)?;
+
+    image::save_buffer(path, &rgb, width as u32, height as u32, image::ColorType::Rgb8)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Converts limited range BT.709 planar YUV 4:2:0 to packed RGB
+fn yuv420_to_rgb(y: &[u8], u: &[u8], v: &[u8], width: usize, height: usize) -> Result<Vec<u8>> {
+    let chroma_width = width.div_ceil(2);
+    let chroma_height = height.div_ceil(2);
+    if y.len() < width * height || u.len() < chroma_width * chroma_height || v.len() < chroma_width * chroma_height {
+        return Err(anyhow!("Frame planes are smaller than the {}x{} resolution", width, height));
+    }
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in 0..height {
+        for col in 0..width {
+            let chroma_index = (row / 2) * chroma_width + col / 2;
+            let y = 1.164 * (y[row * width + col] as f32 - 16.0);
+            let u = u[chroma_index] as f32 - 128.0;
+            let v = v[chroma_index] as f32 - 128.0;
+
+            rgb.push((y + 1.793 * v).clamp(0.0, 255.0) as u8);
+            rgb.push((y - 0.213 * u - 0.533 * v).clamp(0.0, 255.0) as u8);
+            rgb.push((y + 2.112 * u).clamp(0.0, 255.0) as u8);
+        }
+    }
+    Ok(rgb)
+}
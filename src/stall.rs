@@ -0,0 +1,75 @@
+use compositor_render::{Frame, FrameData};
+use tracing::{info, warn};
+
+/// Number of luma bytes sampled per frame, spread evenly over the plane
+const SAMPLE_COUNT: usize = 64;
+/// Limited range black is Y=16, allow a little noise
+const BLACK_LUMA_MAX: u8 = 20;
+/// Consecutive frozen/black frames before warning (3s at 30 fps)
+const STALL_FRAME_THRESHOLD: u64 = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StallKind {
+    Frozen,
+    Black,
+}
+
+/// Detects runs of identical (frozen) or black frames from a sparse sample of the luma plane
+pub struct StallDetector {
+    previous: Option<[u8; SAMPLE_COUNT]>,
+    kind: Option<StallKind>,
+    run: u64,
+    warned: bool,
+}
+
+impl StallDetector {
+    pub fn new() -> Self {
+        Self {
+            previous: None,
+            kind: None,
+            run: 0,
+            warned: false,
+        }
+    }
+
+    pub fn check(&mut self, frame: &Frame) {
+        let luma = match &frame.data {
+            FrameData::PlanarYuv420(planes) | FrameData::PlanarYuvJ420(planes) => &planes.y_plane,
+            _ => return,
+        };
+        if luma.is_empty() {
+            return;
+        }
+
+        let mut sample = [0u8; SAMPLE_COUNT];
+        let step = (luma.len() / SAMPLE_COUNT).max(1);
+        for (i, value) in sample.iter_mut().enumerate() {
+            *value = luma[(i * step).min(luma.len() - 1)];
+        }
+
+        let kind = if sample.iter().all(|value| *value <= BLACK_LUMA_MAX) {
+            Some(StallKind::Black)
+        } else if self.previous == Some(sample) {
+            Some(StallKind::Frozen)
+        } else {
+            None
+        };
+        self.previous = Some(sample);
+
+        if kind.is_some() && kind == self.kind {
+            self.run += 1;
+        } else {
+            if self.warned {
+                info!("Output recovered after {} {:?} frames", self.run, self.kind.unwrap());
+            }
+            self.kind = kind;
+            self.run = u64::from(kind.is_some());
+            self.warned = false;
+        }
+
+        if !self.warned && self.run >= STALL_FRAME_THRESHOLD {
+            self.warned = true;
+            warn!("Output looks stalled: {} consecutive {:?} frames", self.run, self.kind.unwrap());
+        }
+    }
+}
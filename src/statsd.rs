@@ -0,0 +1,54 @@
+use anyhow::{anyhow, Context, Result};
+use std::fmt::Write;
+use std::net::{ToSocketAddrs, UdpSocket};
+use tracing::{info, warn};
+
+/// Pushes gauges to a StatsD server over UDP, one packet per call
+pub struct StatsdClient {
+    addr: String,
+    socket: UdpSocket,
+    logged_error: bool,
+}
+
+impl StatsdClient {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let target = addr
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve StatsD address {}", addr))?
+            .next()
+            .ok_or_else(|| anyhow!("StatsD address {} did not resolve to anything", addr))?;
+        // Bind in the same address family, an IPv4 socket can't reach an IPv6 server
+        let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local).context("Failed to bind the StatsD socket")?;
+        socket
+            .connect(target)
+            .with_context(|| format!("Failed to connect to StatsD address {}", addr))?;
+        info!("Sending memory gauges to statsd://{}", addr);
+        Ok(Self {
+            addr: addr.to_string(),
+            socket,
+            logged_error: false,
+        })
+    }
+
+    /// Sends `name:value|g` lines. Failures are only logged once, the monitor keeps going.
+    ///
+    /// A leading '-' makes StatsD decrement the gauge instead of setting it, so negative
+    /// values (wrapped mallinfo fields) are sent as a reset to 0 followed by the delta.
+    pub fn send_gauges(&mut self, gauges: &[(String, f64)]) {
+        let mut packet = String::new();
+        for (name, value) in gauges {
+            if *value < 0.0 {
+                let _ = writeln!(packet, "{}:0|g", name);
+            }
+            let _ = writeln!(packet, "{}:{}|g", name, value);
+        }
+
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            if !self.logged_error {
+                self.logged_error = true;
+                warn!("Failed to send StatsD gauges to {} ({}), ignoring further errors", self.addr, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use compositor_render::{Frame, FrameData};
+use std::io::{self, Write};
+use std::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::shm::FORMAT_YUV420P;
+
+/// Stream layout (all integers little-endian), for each frame:
+///   0: width u32, 4: height u32, 8: format u32, 12: data length u32, then the frame data
+///
+/// The format values are the same as for the shared memory output.
+pub struct TcpFrameWriter {
+    addr: String,
+    stream: TcpStream,
+    logged_unsupported: bool,
+}
+
+impl TcpFrameWriter {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let stream = TcpStream::connect(addr).with_context(|| format!("Failed to connect to {}", addr))?;
+        info!("Streaming raw frames to tcp://{}", addr);
+        Ok(Self {
+            addr: addr.to_string(),
+            stream,
+            logged_unsupported: false,
+        })
+    }
+
+    /// Sends the frame, blocking until the peer has room for it. An error means the
+    /// connection is unusable.
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let planes = match &frame.data {
+            FrameData::PlanarYuv420(planes) => planes,
+            _ => {
+                if !self.logged_unsupported {
+                    self.logged_unsupported = true;
+                    warn!("Unsupported frame format for TCP output, frames are not sent");
+                }
+                return Ok(());
+            }
+        };
+
+        let data_len = planes.y_plane.len() + planes.u_plane.len() + planes.v_plane.len();
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(&(frame.resolution.width as u32).to_le_bytes());
+        header[4..8].copy_from_slice(&(frame.resolution.height as u32).to_le_bytes());
+        header[8..12].copy_from_slice(&FORMAT_YUV420P.to_le_bytes());
+        header[12..16].copy_from_slice(&(data_len as u32).to_le_bytes());
+
+        self.stream.write_all(&header)?;
+        for plane in [&planes.y_plane, &planes.u_plane, &planes.v_plane] {
+            self.stream.write_all(plane)?;
+        }
+        Ok(())
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::OnceLock;
+use std::thread::{self, JoinHandle};
+
+/// Stack size of the threads spawned by this tool, the std default when unset
+static STACK_SIZE: OnceLock<usize> = OnceLock::new();
+
+/// Sets the stack size used by [`spawn`], must be called before any thread is spawned
+pub fn set_stack_size(bytes: usize) {
+    let _ = STACK_SIZE.set(bytes);
+}
+
+/// Spawns a named thread with the configured stack size
+///
+/// Names are truncated to 15 bytes by the kernel, keep them short.
+pub fn spawn<F, T>(name: &str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut builder = thread::Builder::new().name(name.to_string());
+    if let Some(bytes) = STACK_SIZE.get() {
+        builder = builder.stack_size(*bytes);
+    }
+    builder
+        .spawn(f)
+        .unwrap_or_else(|e| panic!("Failed to spawn {} thread: {}", name, e))
+}
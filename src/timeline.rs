@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Context, Result};
+use compositor_pipeline::Pipeline;
+use compositor_render::{OutputId, RegistryType, RendererId};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::config::{self, Config};
+use crate::events::{self, Event};
+use crate::input;
+use crate::threads;
+
+/// Change applied to the running pipeline at some point of the timeline
+#[derive(Debug, Clone)]
+pub enum TimelineAction {
+    /// Replace the web renderer with a new one showing this URL
+    Web(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TimelineEntry {
+    /// Time since the output was registered
+    pub offset: Duration,
+    pub action: TimelineAction,
+}
+
+/// Loads a timeline file, one `<offset> <action>` per line, sorted by offset
+///
+/// Actions are `web <url>` and `html <path>`. Empty lines and lines starting with
+/// '#' are ignored.
+pub fn load(path: &Path) -> Result<Vec<TimelineEntry>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read timeline {}", path.display()))?;
+
+    let mut entries = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = parse_entry(line)
+            .with_context(|| format!("Invalid timeline {} line {}", path.display(), index + 1))?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|entry| entry.offset);
+    Ok(entries)
+}
+
+fn parse_entry(line: &str) -> Result<TimelineEntry> {
+    let (offset, rest) = split_word(line);
+    let offset = config::parse_duration(offset)?;
+    let (action, argument) = split_word(rest);
+    if argument.is_empty() {
+        return Err(anyhow!("'{}' is missing its argument", action));
+    }
+
+    let action = match action {
+        "web" => TimelineAction::Web(argument.to_string()),
+        "html" => TimelineAction::Web(input::html_file_url(Path::new(argument))?),
+        other => return Err(anyhow!("Unknown action: '{}'. Use web or html", other)),
+    };
+    Ok(TimelineEntry { offset, action })
+}
+
+/// Splits off the first word, however many spaces or tabs follow it; the rest is
+/// trimmed so that an argument can contain spaces
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.find(char::is_whitespace) {
+        Some(end) => (&input[..end], input[end..].trim()),
+        None => (input, ""),
+    }
+}
+
+/// Applies the timeline entries to the output from a dedicated thread
+///
+/// `renderer_id` is the web renderer currently shown by the output.
+pub fn start(
+    pipeline: Arc<Mutex<Pipeline>>,
+    output_id: OutputId,
+    renderer_id: RendererId,
+    config: &Config,
+) {
+    let entries = config.timeline.clone();
+    let resolution = config.resolution;
+    let scene = config.scene.clone();
+    info!("Running a timeline of {} actions", entries.len());
+
+    threads::spawn("timeline", move || {
+        let start = Instant::now();
+        let mut current_renderer = renderer_id;
+        for (index, entry) in entries.into_iter().enumerate() {
+            if let Some(wait) = entry.offset.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+
+            match entry.action {
+                TimelineAction::Web(url) => {
+                    let renderer_id = RendererId(Arc::from(format!("web_renderer_{}", index + 1)));
+                    if let Err(e) = input::register_web_renderer(&pipeline, &renderer_id, &url, resolution) {
+                        warn!("Timeline {:?}: failed to switch to {}: {:#}", entry.offset, url, e);
+                        continue;
+                    }
+
+                    let result = pipeline.lock().unwrap().update_output(
+                        output_id.clone(),
+                        Some(input::build_scene(&renderer_id, &scene)),
+                        None,
+                    );
+                    if let Err(e) = result {
+                        warn!("Timeline {:?}: failed to switch to {}: {}", entry.offset, url, e);
+                        // Nothing shows the new renderer, don't leak its CEF instance
+                        let result = pipeline
+                            .lock()
+                            .unwrap()
+                            .unregister_renderer(&renderer_id, RegistryType::WebRenderer);
+                        if let Err(e) = result {
+                            warn!("Timeline: failed to unregister {}: {}", renderer_id.0, e);
+                        }
+                        continue;
+                    }
+                    info!("Timeline {:?}: switched to {}", entry.offset, url);
+                    events::record(Event::SceneUpdated {
+                        output_id: output_id.0.to_string(),
+                        source: "timeline",
+                    });
+
+                    // The output already shows the new page, a leftover renderer is
+                    // only worth a warning
+                    let previous = std::mem::replace(&mut current_renderer, renderer_id);
+                    let result = pipeline
+                        .lock()
+                        .unwrap()
+                        .unregister_renderer(&previous, RegistryType::WebRenderer);
+                    if let Err(e) = result {
+                        warn!("Timeline: failed to unregister the previous renderer {}: {}", previous.0, e);
+                    }
+                }
+            }
+        }
+        info!("Timeline complete");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entry_allows_aligned_columns() {
+        let entry = parse_entry("5m        web https://example.com").unwrap();
+        assert_eq!(entry.offset, Duration::from_secs(300));
+        assert!(matches!(entry.action, TimelineAction::Web(url) if url == "https://example.com"));
+
+        let entry = parse_entry("1h30m\tweb\t https://example.com/a b").unwrap();
+        assert!(matches!(entry.action, TimelineAction::Web(url) if url == "https://example.com/a b"));
+    }
+
+    #[test]
+    fn parse_entry_requires_an_argument() {
+        assert!(parse_entry("5m web").is_err());
+        assert!(parse_entry("5m").is_err());
+    }
+}